@@ -0,0 +1,25 @@
+use std::{env, process::Command};
+
+fn main() {
+    // building from a tarball has no git, fall back instead of failing
+    let commit = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let commit = match git(&["status", "--porcelain"]) {
+        Some(status) if !status.is_empty() => format!("{commit}-dirty"),
+        _ => commit,
+    };
+    println!("cargo:rustc-env=UTFQ_GIT_COMMIT={commit}");
+    println!(
+        "cargo:rustc-env=UTFQ_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
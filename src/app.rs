@@ -1,9 +1,16 @@
-use std::{collections::HashMap, fs::read_to_string, path::absolute};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::read_to_string,
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf, absolute},
+    time::{Duration, Instant, SystemTime},
+};
 
-use chrono::{Days, NaiveDate};
+use chrono::{Local, NaiveDate};
 use color_eyre::{
     Result,
-    eyre::{Context, ContextCompat},
+    eyre::{Context, ContextCompat, bail},
 };
 use either::Either;
 use hyperrat::Link;
@@ -14,37 +21,135 @@ use ratatui::{
     style::{Style, Stylize},
     text::Span,
 };
+use regex::Regex;
+use tracing::{debug, error, info, warn};
 use url::Url;
 
-use crate::{cli::Cli, parse::parse_file, walk::build_walk_filtered};
+use crate::{
+    calendar::{self, DayCount},
+    cli::{Cli, Format, GroupBy, LinkScheme, Sort, fill_calendar_url},
+    clipboard::{self, Clipboard},
+    crash,
+    datemath::{self, add_days, shift_span},
+    output::{self, CLIPBOARD_PATH, STDIN_PATH, is_pasted},
+    parse::{
+        Finding, FindingKind, Item, has_ignore_pragma, parse_file, retain_logged, uncheck_line,
+    },
+    stats::{SlowFile, Stats},
+    walk::{build_walk_filtered, dedupe_key, is_case_insensitive},
+    watch::{Change, Watcher},
+};
+
+/// How often to check for file changes with --watch.
+const WATCH_POLL: Duration = Duration::from_millis(250);
+
+/// Parsed items keyed by file path, iterated in path order.
+type Collected = BTreeMap<PathBuf, Vec<Item>>;
+
+/// What parsing one file gave, kept between reloads with --watch.
+struct Parsed {
+    items: Vec<Item>,
+    findings: Vec<Finding>,
+    /// Items counted by --fail-if-overdue.
+    overdue: usize,
+    elapsed: Duration,
+    /// The contents, kept when --forecast may parse them for other dates.
+    source: Option<String>,
+}
 
 pub struct App {
     cli: Cli,
     is_running: bool,
     date: NaiveDate,
-    lines: Vec<Either<String, Link<'static>>>,
+    lines: Vec<Either<Span<'static>, Link<'static>>>,
     offset: usize,
     /// Whether to show done items.
     done: bool,
+    /// Whether to color items by urgency.
+    color: bool,
+    /// Whether to emit OSC 8 hyperlinks.
+    hyperlinks: bool,
+    /// All --grep patterns combined.
+    grep: Option<Regex>,
+    /// Number of visible items in the last scan.
+    visible_count: usize,
+    /// Number of visible overdue items in the last scan.
+    overdue_count: usize,
+    /// Number of paths that could not be walked or read in the last scan.
+    error_count: usize,
+    /// Lines --pedantic warns about in the last scan.
+    findings: Vec<(PathBuf, Finding)>,
+    /// Parse time and item count of each file in the last reload.
+    timings: Vec<(PathBuf, Duration, usize)>,
+    /// Modification time of each file in the last reload.
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+    /// Markdown read from stdin or the clipboard under its key, kept for reloads.
+    pasted: Option<(&'static str, String)>,
+    /// Watcher of the roots with --watch, started with the TUI.
+    watcher: Option<Watcher>,
+    /// Files parsed by the last scan with --watch by absolute path, with their modification
+    /// time and `None` when skipped by their pragma, dropped once they change.
+    parsed: HashMap<PathBuf, (Option<SystemTime>, Option<Parsed>)>,
 }
 
 impl App {
-    pub fn new(cli: Cli) -> Self {
-        Self {
-            date: cli.date,
+    pub fn new(cli: Cli) -> Result<Self> {
+        Self::with_clipboard(cli, &mut clipboard::System)
+    }
+
+    /// Like [`App::new`], reading --clipboard from `clipboard`.
+    pub fn with_clipboard(cli: Cli, clipboard: &mut dyn Clipboard) -> Result<Self> {
+        let pasted = match () {
+            _ if cli.stdin => {
+                let text = io::read_to_string(io::stdin()).wrap_err("failed to read stdin")?;
+                Some((STDIN_PATH, text))
+            }
+            _ if cli.clipboard => Some((CLIPBOARD_PATH, clipboard.text()?)),
+            _ => None,
+        };
+        Ok(Self {
+            date: cli.date(),
             done: cli.done,
+            color: cli.color.enabled(),
+            hyperlinks: cli.hyperlinks.enabled(),
+            grep: cli.grep_regex(),
+            visible_count: Default::default(),
+            overdue_count: Default::default(),
+            error_count: Default::default(),
+            findings: Default::default(),
             cli,
             is_running: true,
             lines: Default::default(),
             offset: Default::default(),
-        }
+            timings: Default::default(),
+            modified: Default::default(),
+            pasted,
+            watcher: None,
+            parsed: Default::default(),
+        })
     }
 
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        if self.cli.watch {
+            self.watcher = Some(Watcher::new(&self.cli.paths)?);
+        }
         self.reload()?;
         while self.is_running {
             terminal.draw(|frame| self.render(frame))?;
-            self.handle_event()?;
+            // without a watcher, block until the next key
+            let ready = match &self.watcher {
+                None => true,
+                Some(_) => event::poll(WATCH_POLL).context("event poll failed")?,
+            };
+            if ready {
+                self.handle_event()?;
+            } else if let Some(change) = self.watcher.as_ref().and_then(Watcher::changed) {
+                match change {
+                    Change::All => self.parsed.clear(),
+                    Change::Files(paths) => self.parsed.retain(|path, _| !paths.contains(path)),
+                }
+                self.refresh();
+            }
         }
 
         Ok(())
@@ -54,9 +159,9 @@ impl App {
         let area = frame.area();
         for (i, w) in (0..area.height).zip(self.lines.iter().skip(self.offset)) {
             match w {
-                Either::Left(string) => {
+                Either::Left(span) => {
                     frame.render_widget(
-                        Span::from(string),
+                        span,
                         Rect {
                             x: area.x,
                             y: area.y + i,
@@ -80,15 +185,23 @@ impl App {
             }
         }
         if area.width > 10 {
-            frame.render_widget(
-                Span::from(self.date.to_string()).reversed(),
-                Rect {
-                    x: area.x + area.width - 10,
-                    y: area.y,
-                    width: 10,
-                    height: 1,
-                },
-            );
+            let rect = Rect {
+                x: area.x + area.width - 10,
+                y: area.y,
+                width: 10,
+                height: 1,
+            };
+            match &self.cli.calendar_url {
+                Some(template) if self.hyperlinks => frame.render_widget(
+                    Link::new(
+                        self.date.to_string(),
+                        fill_calendar_url(template, self.date),
+                    )
+                    .style(Style::default().reversed()),
+                    rect,
+                ),
+                _ => frame.render_widget(Span::from(self.date.to_string()).reversed(), rect),
+            }
         }
     }
 
@@ -97,9 +210,7 @@ impl App {
             match key_event.code {
                 KeyCode::Char('q') => self.is_running = false,
                 KeyCode::Up | KeyCode::Char('k') => {
-                    if self.offset > 0 {
-                        self.offset -= 1;
-                    }
+                    self.offset = self.offset.saturating_sub(1);
                 }
                 KeyCode::Down | KeyCode::Char('j') => {
                     self.offset += 1;
@@ -110,18 +221,12 @@ impl App {
                 }
                 KeyCode::Left | KeyCode::Char('h') => {
                     self.offset = 0;
-                    self.date = self
-                        .date
-                        .checked_sub_days(Days::new(1))
-                        .context("date out of range")?;
+                    self.date = add_days(self.date, -1).context("date out of range")?;
                     self.reload()?;
                 }
                 KeyCode::Right | KeyCode::Char('l') => {
                     self.offset = 0;
-                    self.date = self
-                        .date
-                        .checked_add_days(Days::new(1))
-                        .context("date out of range")?;
+                    self.date = add_days(self.date, 1).context("date out of range")?;
                     self.reload()?;
                 }
                 KeyCode::Char('d') => {
@@ -138,30 +243,753 @@ impl App {
     }
 
     pub fn reload(&mut self) -> Result<()> {
-        let mut collected = HashMap::new();
+        self.parsed.clear();
+        self.refresh();
+        Ok(())
+    }
+
+    /// Scan again, parsing only the files not kept from the last scan.
+    fn refresh(&mut self) {
+        let (collected, banner) = self.scan();
+        self.fill_lines(&collected, banner);
+    }
+
+    /// Replace the lines shown by the TUI and --format text.
+    fn fill_lines(&mut self, collected: &Collected, banner: Option<String>) {
+        self.lines.clear();
+        if self.watcher.is_some() {
+            let updated = format!("updated {}", Local::now().format("%H:%M:%S"));
+            self.lines.push(Either::Left(Span::from(updated).dim()));
+        }
+        if let Some(banner) = banner {
+            self.lines.push(Either::Left(Span::from(banner)));
+        }
+
+        let keys = self.sorted_keys(collected);
+        match (self.cli.sort, self.cli.group_by) {
+            (_, GroupBy::Date) => self.push_by_date(collected),
+            (_, GroupBy::Overdue) => self.push_by_overdue(collected),
+            (_, GroupBy::Assignee) => self.push_by_assignee(collected),
+            // a global order across files does not fit in sections
+            (Sort::Date | Sort::Text, _) => self.push_flat(collected),
+            (_, GroupBy::File) => self.push_by_file(&keys, collected),
+            (_, GroupBy::Dir(depth)) => self.push_by_dir(depth, &keys, collected),
+        }
+    }
+
+    /// Whether the last scan trips --fail-if-overdue or --fail-if-any.
+    pub fn failed(&self) -> bool {
+        (self.cli.fail_if_overdue && self.overdue_count > 0)
+            || (self.cli.fail_if_any && self.visible_count > 0)
+    }
+
+    /// Whether the last scan skipped paths it could not walk or read.
+    pub fn had_errors(&self) -> bool {
+        self.error_count > 0
+    }
+
+    /// Print the visible items in a machine format or as text instead of running the TUI.
+    pub fn print(&mut self, format: Format) -> Result<()> {
+        let (collected, banner) = self.scan();
+        if format == Format::Text {
+            // links only make sense in a terminal
+            self.hyperlinks = false;
+            self.fill_lines(&collected, banner);
+        }
+        let entries = self.entries(&collected);
+        // keyed like the entries
+        let modified: HashMap<_, _> = (self.modified.iter())
+            .map(|(path, time)| (path.strip_prefix(".").unwrap_or(path), *time))
+            .collect();
+
+        if let Some(dir) = &self.cli.out {
+            if format != Format::Ics {
+                bail!("--out is only supported with --format ics");
+            }
+            output::write_ics_days(dir, &entries, self.queried_days())?;
+            return Ok(());
+        }
+
+        output::to_stdout(|w| match format {
+            Format::Json => {
+                output::write_json(w, &entries, self.date, &self.cli.overdue_buckets, &modified)
+            }
+            Format::Ics => output::write_ics(w, &entries),
+            Format::Csv => output::write_csv(w, &entries, !self.cli.no_header),
+            Format::Todotxt => output::write_todotxt(w, &entries),
+            Format::Text => self.lines.iter().try_for_each(|line| {
+                let span = line.as_ref().left().expect("no links without hyperlinks");
+                writeln!(w, "{}", span.content)
+            }),
+        })?;
+
+        Ok(())
+    }
+
+    /// Days whose items the last scan looked at, none when it skipped dated items.
+    fn queried_days(&self) -> Option<RangeInclusive<NaiveDate>> {
+        if self.cli.malformed || self.cli.only_someday {
+            None
+        } else if self.cli.all {
+            Some(NaiveDate::MIN..=NaiveDate::MAX)
+        } else if self.cli.overdue || self.cli.min_overdue.is_some() {
+            datemath::today()
+                .pred_opt()
+                .map(|last| NaiveDate::MIN..=last)
+        } else {
+            Some(self.date..=self.date)
+        }
+    }
+
+    /// Print the number of visible items per file and in total.
+    pub fn print_count(&mut self) -> Result<()> {
+        let (collected, _) = self.scan();
+        let today = datemath::today();
+        let (mut total, mut overdue, mut hidden) = (0, 0, 0);
 
-        for result in build_walk_filtered() {
+        output::to_stdout(|w| {
+            for path in self.sorted_keys(&collected) {
+                let items = collected.get(path).unwrap();
+                let visible = items.iter().filter(|i| self.done || !i.done).count();
+                hidden += items.len() - visible;
+                overdue += items
+                    .iter()
+                    .filter(|i| i.overdue_days(today).is_some())
+                    .count();
+                if visible == 0 {
+                    continue;
+                }
+                total += visible;
+                let relative_path = path.strip_prefix(".").unwrap_or(path);
+                writeln!(w, "{visible:>5}  {}", relative_path.display())?;
+            }
+            match self.done {
+                true => writeln!(w, "{total} total ({overdue} overdue)"),
+                false => writeln!(w, "{total} total ({overdue} overdue, {hidden} done hidden)"),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// Print the month grid of --calendar.
+    pub fn print_calendar(&mut self, month: Option<NaiveDate>) -> Result<()> {
+        let (collected, _) = self.scan();
+        let today = datemath::today();
+
+        let mut counts: BTreeMap<NaiveDate, DayCount> = BTreeMap::new();
+        for item in collected.values().flatten() {
+            let Some(date) = item.date.filter(|_| self.done || !item.done) else {
+                continue;
+            };
+            let count = counts.entry(date).or_default();
+            count.items += 1;
+            count.overdue += item.overdue_days(today).is_some() as usize;
+        }
+
+        let month = month.unwrap_or(self.date);
+        let week_start = self.cli.week_start.weekday();
+        output::to_stdout(|w| {
+            calendar::write_month(w, month, &counts, today, week_start, self.color)
+        })?;
+
+        Ok(())
+    }
+
+    /// Print the --week agenda, one section per day with the items on it.
+    pub fn print_week(&mut self, weeks: i64) -> Result<()> {
+        let (collected, _) = self.scan();
+        let entries = self.entries(&collected);
+        let week = self.date.week(self.cli.week_start.weekday());
+        let (start, end) = weeks
+            .checked_mul(7)
+            .and_then(|days| shift_span(week.first_day(), week.last_day(), days))
+            .context("week out of range")?;
+
+        output::to_stdout(|w| {
+            for day in start.iter_days().take_while(|&day| day <= end) {
+                writeln!(w, "{}", day.format("%A %Y-%m-%d"))?;
+                for (path, item) in entries.iter().filter(|(_, i)| i.date == Some(day)) {
+                    let text = self.item_text(item);
+                    writeln!(w, "  {text}  ({}:{})", path.display(), item.line)?;
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    /// Print the visible items through --template.
+    pub fn print_template(&mut self) -> Result<()> {
+        let (collected, _) = self.scan();
+        let entries = self.entries(&collected);
+        let template = self.cli.template.as_deref().unwrap_or_default();
+        let buckets = &self.cli.overdue_buckets;
+        output::to_stdout(|w| output::write_template(w, &entries, template, self.date, buckets))?;
+        Ok(())
+    }
+
+    /// Print aggregate numbers over all items, done ones included.
+    pub fn print_stats(&mut self, format: Option<Format>) -> Result<()> {
+        if format.is_some_and(|format| format != Format::Json) {
+            bail!("--stats only supports --format json");
+        }
+        let (collected, _) = self.scan();
+        let mut stats = Stats::new(collected.values().flatten(), datemath::today());
+        stats.slowest_files = self
+            .slowest_files(self.cli.slow_files.unwrap_or(10))
+            .map(|(path, duration, items)| SlowFile {
+                path: path.to_string_lossy().into_owned(),
+                ms: duration.as_secs_f64() * 1000.0,
+                items,
+            })
+            .collect();
+
+        output::to_stdout(|w| match format {
+            Some(_) => {
+                serde_json::to_writer_pretty(&mut *w, &stats)?;
+                writeln!(w)
+            }
+            None => stats.write_table(w),
+        })?;
+
+        Ok(())
+    }
+
+    /// Whether --forecast may widen the window, only where its banner is shown.
+    fn forecasts(&self) -> bool {
+        let cli = &self.cli;
+        let tui = cli.format.is_none() && !cli.count && !cli.stats && cli.template.is_none();
+        let text = cli.format == Some(Format::Text);
+        cli.forecast && !cli.all && (tui || text)
+    }
+
+    /// Walk and parse all files, returning the items and a banner for --forecast.
+    fn scan(&mut self) -> (Collected, Option<String>) {
+        // file contents are dropped right after parsing, only kept when
+        // --forecast may need to parse them again for other dates
+        let keep_sources = self.forecasts();
+        let mut sources = vec![];
+        let mut collected = Collected::new();
+        // files reachable from several roots are only handled once
+        let mut seen = HashSet::new();
+        let roots: Vec<_> = self
+            .cli
+            .paths
+            .iter()
+            .map(|root| {
+                let ignore_case = self.cli.case_insensitive_paths || is_case_insensitive(root);
+                (root, ignore_case)
+            })
+            .collect();
+        // --fail-if-overdue looks at every date, not only the viewed one
+        let overdue_cli = self.cli.fail_if_overdue.then(|| {
+            let mut cli = self.cli.clone();
+            (cli.overdue, cli.malformed, cli.pedantic) = (true, false, false);
+            cli
+        });
+        let mut overdue_count = 0;
+        let mut findings = vec![];
+        // files parsed before and unchanged since are kept
+        let mut parsed = std::mem::take(&mut self.parsed);
+        self.modified.clear();
+        self.timings.clear();
+        self.error_count = 0;
+        self.findings.clear();
+        for root in &self.cli.paths {
+            info!("walking {}", root.display());
+        }
+
+        if let Some((key, text)) = &self.pasted {
+            let path = PathBuf::from(key);
+            let _guard = crash::processing(&path);
+            let items = parse_file(&self.cli, self.date, text, &mut findings);
+            collected.insert(path.clone(), items);
+            if let Some(cli) = &overdue_cli {
+                overdue_count += self.count_overdue(cli, text);
+            }
+            let found = findings.drain(..).map(|finding| (path.clone(), finding));
+            self.findings.extend(found);
+            if keep_sources {
+                sources.push((path, text.clone()));
+            }
+        }
+
+        let walk = build_walk_filtered(&self.cli.paths, &self.cli.include, &self.cli.exclude);
+        for result in walk {
             match result {
                 Ok(entry) => {
                     // only handle file
                     let path = entry.path();
-                    let Ok(string) = read_to_string(path) else {
+                    let ignore_case = roots
+                        .iter()
+                        .find(|(root, _)| path.starts_with(root))
+                        .is_some_and(|&(_, ignore_case)| ignore_case);
+                    if !seen.insert(dedupe_key(path, ignore_case)) {
+                        debug!(
+                            "skipping {}: already seen from another root",
+                            path.display()
+                        );
                         continue;
+                    }
+                    let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                    let key = absolute(path).unwrap_or_else(|_| path.to_path_buf());
+                    let kept = (parsed.remove(&key)).filter(|&(before, _)| before == modified);
+                    let file = match kept {
+                        Some((_, file)) => file,
+                        None => match self.parse_path(path, overdue_cli.as_ref(), keep_sources) {
+                            Ok(file) => file,
+                            Err(err) => {
+                                error!("cannot read {}: {err}", path.display());
+                                self.error_count += 1;
+                                continue;
+                            }
+                        },
                     };
-                    let new_items = parse_file(&self.cli, self.date, &string);
-                    collected.insert(path.to_path_buf(), new_items);
+
+                    match &file {
+                        None => debug!("skipped {}: utfq: ignore pragma", path.display()),
+                        Some(file) => {
+                            self.modified.insert(path.to_path_buf(), modified);
+                            self.timings
+                                .push((path.to_path_buf(), file.elapsed, file.items.len()));
+                            collected.insert(path.to_path_buf(), file.items.clone());
+                            overdue_count += file.overdue;
+                            let found = file
+                                .findings
+                                .iter()
+                                .map(|&finding| (path.to_path_buf(), finding));
+                            self.findings.extend(found);
+                            if let Some(source) = &file.source {
+                                sources.push((path.to_path_buf(), source.clone()));
+                            }
+                        }
+                    }
+                    if self.watcher.is_some() {
+                        self.parsed.insert(key, (modified, file));
+                    }
+                }
+                Err(err) => {
+                    error!("{err}");
+                    self.error_count += 1;
                 }
-                Err(err) => eprintln!("ERROR: {}", err),
             }
         }
 
-        self.lines.clear();
+        let mut banner = None;
+        let mut widened = false;
+        self.apply_grep(&mut collected);
+
+        // widen the window step by step until something shows up
+        if keep_sources && !self.any_visible(&collected) {
+            for &days in &self.cli.forecast_steps {
+                let mut more = Collected::new();
+                for offset in 1..=days {
+                    let Some(date) = add_days(self.date, offset as i64) else {
+                        break;
+                    };
+                    for (path, items) in self.collect(&sources, date) {
+                        more.entry(path).or_default().extend(items);
+                    }
+                }
+                self.apply_grep(&mut more);
+                if self.any_visible(&more) {
+                    if !self.cli.quiet {
+                        banner = Some(format!(
+                            "nothing on {}; showing next {days} days",
+                            self.date
+                        ));
+                    }
+                    (collected, widened) = (more, true);
+                    break;
+                }
+            }
+        }
+
+        self.apply_limits(&mut collected);
 
+        let shown = collected
+            .values()
+            .flatten()
+            .filter(|item| self.done || !item.done)
+            .count();
+        info!("{shown} items to show");
+        // --fail-if-any only looks at the viewed date, nothing showed up there
+        self.visible_count = match widened {
+            true => 0,
+            false => shown,
+        };
+        self.overdue_count = overdue_count;
+
+        (collected, banner)
+    }
+
+    /// Number of open items in `text` dated before today and matching --grep.
+    /// Read and parse the file at `path`, `None` if its pragma says to skip it.
+    fn parse_path(
+        &self,
+        path: &Path,
+        overdue_cli: Option<&Cli>,
+        keep_sources: bool,
+    ) -> io::Result<Option<Parsed>> {
+        let string = read_to_string(path)?;
+        if has_ignore_pragma(&string) {
+            return Ok(None);
+        }
+        let _guard = crash::processing(path);
+        let start = Instant::now();
+        let mut findings = vec![];
+        let items = parse_file(&self.cli, self.date, &string, &mut findings);
+        let elapsed = start.elapsed();
+        debug!("{}: {} items", path.display(), items.len());
+        let overdue = overdue_cli.map_or(0, |cli| self.count_overdue(cli, &string));
+        let relevant = string.contains("<agmd:") || self.cli.html_comment_dates;
+        Ok(Some(Parsed {
+            items,
+            findings,
+            overdue,
+            elapsed,
+            source: (keep_sources && relevant).then_some(string),
+        }))
+    }
+
+    fn count_overdue(&self, overdue_cli: &Cli, text: &str) -> usize {
+        let items = parse_file(overdue_cli, self.date, text, &mut vec![]);
+        items
+            .iter()
+            .filter(|item| {
+                (self.grep.as_ref())
+                    .is_none_or(|grep| grep.is_match(&item.text) != self.cli.invert_grep)
+            })
+            .count()
+    }
+
+    /// Warn about the lines --pedantic found in the last scan.
+    pub fn report_findings(&self) {
+        for (path, finding) in &self.findings {
+            warn!("{}", describe(path, finding));
+        }
+    }
+
+    /// Ask whether each early completion of the last scan should be unchecked,
+    /// and uncheck the confirmed ones in their files. Other findings are only reported.
+    pub fn review_early(&self) -> Result<()> {
+        let mut stderr = io::stderr();
+        for (path, finding) in &self.findings {
+            if finding.kind != FindingKind::DoneEarly {
+                warn!("{}", describe(path, finding));
+                continue;
+            }
+            write!(stderr, "{} — uncheck? [y/N] ", describe(path, finding))?;
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer)? == 0 {
+                break;
+            }
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                continue;
+            }
+            let text = read_to_string(path)
+                .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+            let Some(text) = uncheck_line(&text, finding.line) else {
+                warn!("{}:{}: no longer checked", path.display(), finding.line);
+                continue;
+            };
+            output::write_atomic(path, |w| w.write_all(text.as_bytes()))
+                .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Drop items not matching --grep, or matching it with --invert-grep.
+    fn apply_grep(&self, collected: &mut Collected) {
+        let Some(grep) = &self.grep else {
+            return;
+        };
+        for items in collected.values_mut() {
+            retain_logged(items, "--grep", |item| {
+                grep.is_match(&item.text) != self.cli.invert_grep
+            });
+        }
+    }
+
+    /// Drop items beyond --limit-per-file and --limit, keeping the earliest dates.
+    fn apply_limits(&self, collected: &mut Collected) {
+        let visible = |item: &Item| self.done || !item.done;
+
+        if let Some(limit) = self.cli.limit_per_file {
+            for items in collected.values_mut() {
+                let mut kept = 0;
+                items.retain(|item| {
+                    kept += visible(item) as usize;
+                    kept <= limit
+                });
+            }
+        }
+
+        if let Some(limit) = self.cli.limit {
+            let mut ranked: Vec<_> = collected
+                .iter()
+                .flat_map(|(path, items)| {
+                    items
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, item)| visible(item))
+                        .map(move |(n, item)| ((item.date.is_none(), item.date), path.clone(), n))
+                })
+                .collect();
+            ranked.sort();
+            let keep: HashSet<_> = ranked
+                .into_iter()
+                .take(limit)
+                .map(|(_, path, n)| (path, n))
+                .collect();
+            for (path, items) in collected.iter_mut() {
+                let mut n = 0;
+                items.retain(|_| {
+                    n += 1;
+                    keep.contains(&(path.clone(), n - 1))
+                });
+            }
+        }
+    }
+
+    /// File paths in the order of their sections.
+    fn sorted_keys<'a>(&self, collected: &'a Collected) -> Vec<&'a PathBuf> {
+        // already in path order
         let mut keys: Vec<_> = collected.keys().collect();
-        keys.sort();
+        match self.cli.sort {
+            Sort::Path | Sort::Date | Sort::Text => {}
+            // most recently modified first, unknown last
+            Sort::FileMtime => keys.sort_by(|a, b| {
+                let modified_a = self.modified.get(*a).copied().flatten();
+                let modified_b = self.modified.get(*b).copied().flatten();
+                modified_b.cmp(&modified_a).then(a.cmp(b))
+            }),
+        }
+        keys
+    }
+
+    /// Visible items with their relative paths, in output order.
+    fn entries<'a>(&self, collected: &'a Collected) -> Vec<(&'a Path, &'a Item)> {
+        let mut entries = vec![];
+        for path in self.sorted_keys(collected) {
+            let relative_path = path.strip_prefix(".").unwrap_or(path);
+            for item in collected.get(path).unwrap() {
+                if self.done || !item.done {
+                    entries.push((relative_path, item));
+                }
+            }
+        }
+
+        // stable sorts, equal items keep their file order
+        match self.cli.sort {
+            Sort::Date => entries.sort_by_key(|(_, item)| (item.date.is_none(), item.date)),
+            Sort::Text => entries.sort_by_cached_key(|(_, item)| item.summary()),
+            Sort::Path | Sort::FileMtime => {}
+        }
+        entries
+    }
+
+    /// The `n` files of the last reload that took longest to parse, slowest first.
+    fn slowest_files(&self, n: usize) -> impl Iterator<Item = (&Path, Duration, usize)> {
+        let mut timings: Vec<_> = self.timings.iter().collect();
+        timings.sort_by_key(|&(_, duration, _)| std::cmp::Reverse(*duration));
+        timings.into_iter().take(n).map(|(path, duration, count)| {
+            let relative_path = path.strip_prefix(".").unwrap_or(path);
+            (relative_path, *duration, *count)
+        })
+    }
+
+    /// Print the slowest files of the last reload to stderr, if asked by `--slow-files`.
+    pub fn print_slow_files(&self) {
+        let Some(n) = self.cli.slow_files else {
+            return;
+        };
+        output::to_stderr(|w| {
+            for (path, duration, count) in self.slowest_files(n) {
+                writeln!(
+                    w,
+                    "{:>10.3} ms {count:>6} items  {}",
+                    duration.as_secs_f64() * 1000.0,
+                    path.display()
+                )?;
+            }
+            Ok(())
+        });
+    }
+
+    fn collect(&self, sources: &[(PathBuf, String)], date: NaiveDate) -> Collected {
+        sources
+            .iter()
+            .map(|(path, string)| {
+                let _guard = crash::processing(path);
+                let items = parse_file(&self.cli, date, string, &mut vec![]);
+                (path.clone(), items)
+            })
+            .collect()
+    }
+
+    /// Whether any item would be shown with the current done visibility.
+    fn any_visible(&self, collected: &Collected) -> bool {
+        collected
+            .values()
+            .flatten()
+            .any(|item| self.done || !item.done)
+    }
+
+    /// A bold section header, linked to `url` when hyperlinks are enabled.
+    fn header(&self, text: String, url: &Url) -> Either<Span<'static>, Link<'static>> {
+        match self.hyperlinks {
+            true => Either::Right(Link::new(text, url.to_string()).style(Style::default().bold())),
+            false => Either::Left(Span::from(text).bold()),
+        }
+    }
+
+    /// The item line, followed by where its date comes from, its date
+    /// relative to today with --relative and the headings it is under.
+    fn item_text(&self, item: &Item) -> String {
+        let mut text = item.text.clone();
+        if let (Some(source), Some(date)) = (item.date_source, item.date) {
+            text = format!("{text}  (from {source}: {date})");
+        }
+        if let Some(date) = item.date.filter(|_| self.cli.relative) {
+            let relative = output::relative_due(date, item.done, datemath::today());
+            text = format!("{text}  ({relative})");
+        }
+        if let Some(error) = &item.error {
+            text = format!("{text}  ({error})");
+        }
+        if !self.cli.no_context && !item.context.is_empty() {
+            text = format!("{text}  — {}", item.context.join(" > "));
+        }
+        text
+    }
+
+    /// An item line, linked to the item's source line when hyperlinks are enabled.
+    fn item_line(
+        &self,
+        text: String,
+        path: &Path,
+        item: &Item,
+    ) -> Either<Span<'static>, Link<'static>> {
+        let style = self.item_style(item);
+        if !self.hyperlinks || is_pasted(path) {
+            return Either::Left(Span::styled(text, style));
+        }
+        let path = absolute(path).unwrap();
+        let url = match self.cli.link_scheme {
+            LinkScheme::File => format!("{}#L{}", Url::from_file_path(&path).unwrap(), item.line),
+            LinkScheme::Vscode => format!("vscode://file{}:{}", path.display(), item.line),
+        };
+        Either::Right(Link::new(text, url).style(style))
+    }
+
+    /// Red when overdue, yellow when on today, dimmed when done.
+    fn item_style(&self, item: &Item) -> Style {
+        if !self.color {
+            return Style::default();
+        }
+        let today = datemath::today();
+        match item.date {
+            _ if item.done => Style::default().dim(),
+            Some(date) if date < today => Style::default().red(),
+            Some(date) if date == today => Style::default().yellow(),
+            _ => Style::default(),
+        }
+    }
+
+    fn push_flat(&mut self, collected: &Collected) {
+        for (path, item) in self.entries(collected) {
+            let text = format!("{}:{}: {}", path.display(), item.line, self.item_text(item));
+            self.lines.push(self.item_line(text, path, item));
+        }
+    }
 
+    fn push_by_date(&mut self, collected: &Collected) {
+        // undated items go last
+        let mut days: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (path, item) in self.entries(collected) {
+            days.entry((item.date.is_none(), item.date))
+                .or_default()
+                .push((path, item));
+        }
+
+        for ((_, date), entries) in days {
+            let header = match date {
+                Some(date) => date.format("%Y-%m-%d %A").to_string(),
+                None => "undated".to_string(),
+            };
+            self.lines.push(Either::Left(Span::from(header).bold()));
+            for (path, item) in entries {
+                let text = format!(
+                    "  {}  ({}:{})",
+                    self.item_text(item),
+                    path.display(),
+                    item.line
+                );
+                self.lines.push(self.item_line(text, path, item));
+            }
+        }
+    }
+
+    fn push_by_overdue(&mut self, collected: &Collected) {
+        let buckets = &self.cli.overdue_buckets;
+        let mut sections: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (path, item) in self.entries(collected) {
+            let rank = output::overdue_rank(item.overdue_days(self.date), buckets);
+            sections.entry(rank).or_default().push((path, item));
+        }
+
+        // the worst debt on top
+        for (rank, entries) in sections.into_iter().rev() {
+            let header = output::bucket_label(rank, buckets);
+            self.lines.push(Either::Left(Span::from(header).bold()));
+            for (path, item) in entries {
+                let text = format!(
+                    "  {}  ({}:{})",
+                    self.item_text(item),
+                    path.display(),
+                    item.line
+                );
+                self.lines.push(self.item_line(text, path, item));
+            }
+        }
+    }
+
+    fn push_by_assignee(&mut self, collected: &Collected) {
+        // unassigned items go last, an item shows under each of its names
+        let mut people: BTreeMap<_, (String, Vec<_>)> = BTreeMap::new();
+        for (path, item) in self.entries(collected) {
+            if item.assignees.is_empty() {
+                let section = people.entry((true, String::new()));
+                let (_, entries) = section.or_insert_with(|| ("unassigned".to_string(), vec![]));
+                entries.push((path, item));
+            }
+            for name in &item.assignees {
+                let section = people.entry((false, name.to_lowercase()));
+                let (_, entries) = section.or_insert_with(|| (format!("@{name}"), vec![]));
+                entries.push((path, item));
+            }
+        }
+
+        for (header, entries) in people.into_values() {
+            self.lines.push(Either::Left(Span::from(header).bold()));
+            for (path, item) in entries {
+                let text = format!(
+                    "  {}  ({}:{})",
+                    self.item_text(item),
+                    path.display(),
+                    item.line
+                );
+                self.lines.push(self.item_line(text, path, item));
+            }
+        }
+    }
+
+    fn push_by_file(&mut self, keys: &[&PathBuf], collected: &Collected) {
         for path in keys {
-            let items = collected.get(path).unwrap();
+            let items = collected.get(*path).unwrap();
             if items.is_empty() {
                 continue;
             }
@@ -169,21 +997,196 @@ impl App {
             let url = Url::from_file_path(absolute(path).unwrap()).unwrap();
 
             // only show path if have one more item
-            if self.done || items.iter().any(|i| !i.0) {
-                self.lines.push(Either::Right(
-                    Link::new(relative_path.display().to_string(), url.to_string())
-                        .style(Style::default().bold()),
-                ));
+            if self.done || items.iter().any(|i| !i.done) {
+                let header = match self.cli.show_mtime {
+                    // stdin and the clipboard have no file to link to
+                    _ if is_pasted(path) => {
+                        let header = format!("==== {} ====", path.display());
+                        Either::Left(Span::from(header).bold())
+                    }
+                    true => self.header(
+                        format!(
+                            "{}  edited {}",
+                            relative_path.display(),
+                            humanize_since(self.modified.get(*path).copied().flatten())
+                        ),
+                        &url,
+                    ),
+                    false => self.header(relative_path.display().to_string(), &url),
+                };
+                self.lines.push(header);
             }
 
-            for (done, item) in items {
-                if !self.done && *done {
+            for item in items {
+                if !self.done && item.done {
                     continue;
                 }
-                self.lines.push(Either::Left(format!("  {}", item.trim())));
+                let text = format!("  {}: {}", item.line, self.item_text(item));
+                self.lines.push(self.item_line(text, path, item));
             }
         }
+    }
+
+    fn push_by_dir(&mut self, depth: usize, keys: &[&PathBuf], collected: &Collected) {
+        let mut groups: BTreeMap<PathBuf, Vec<&PathBuf>> = BTreeMap::new();
+        for path in keys {
+            groups
+                .entry(group_dir(&self.cli.paths, path, depth))
+                .or_default()
+                .push(*path);
+        }
 
-        Ok(())
+        for (dir, paths) in groups {
+            let items = paths.iter().flat_map(|path| collected.get(*path).unwrap());
+            let open = items.clone().filter(|i| !i.done).count();
+            let overdue = (items.clone())
+                .filter(|i| i.overdue_days(self.date).is_some())
+                .count();
+            let done = items.filter(|i| i.done).count();
+
+            // only show dir if have one more item
+            if open == 0 && (!self.done || done == 0) {
+                continue;
+            }
+
+            let url = Url::from_directory_path(absolute(&dir).unwrap()).unwrap();
+            let shown = (dir.strip_prefix(".").ok())
+                .filter(|shown| !shown.as_os_str().is_empty())
+                .unwrap_or(&dir);
+            let header = match self.done {
+                true => format!(
+                    "{} ({open} open, {overdue} overdue, {done} done)",
+                    shown.display()
+                ),
+                false => format!("{} ({open} open, {overdue} overdue)", shown.display()),
+            };
+            self.lines.push(self.header(header, &url));
+
+            for path in paths {
+                let relative_path = path.strip_prefix(".").unwrap_or(path);
+                let file = path.strip_prefix(&dir).unwrap_or(relative_path);
+                for item in collected.get(path).unwrap() {
+                    if !self.done && item.done {
+                        continue;
+                    }
+                    let text = format!(
+                        "  {}:{}: {}",
+                        file.display(),
+                        item.line,
+                        self.item_text(item)
+                    );
+                    self.lines.push(self.item_line(text, path, item));
+                }
+            }
+        }
+    }
+}
+
+/// What is wrong with the line of `finding` in `path`, like `a.md:3: checked but due ...`.
+fn describe(path: &Path, finding: &Finding) -> String {
+    let relative_path = path.strip_prefix(".").unwrap_or(path);
+    let date = finding.date;
+    let years = datemath::days_between(datemath::today(), date).abs() / 365;
+    let problem = match finding.kind {
+        FindingKind::DoneEarly => format!("checked but due {date}, completed early?"),
+        FindingKind::FarFuture => format!("date {date} is {years} years away — typo?"),
+        FindingKind::FarPast => format!("date {date} was {years} years ago — typo?"),
+    };
+    format!("{}:{}: {problem}", relative_path.display(), finding.line)
+}
+
+/// Humanize the time elapsed since `time`, like `3h ago`.
+fn humanize_since(time: Option<SystemTime>) -> String {
+    let Some(elapsed) = time.and_then(|t| t.elapsed().ok()) else {
+        return "—".to_string();
+    };
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..60 => format!("{minutes}m ago"),
+        60..1440 => format!("{}h ago", minutes / 60),
+        _ => format!("{}d ago", minutes / 1440),
+    }
+}
+
+/// The root `path` was walked from, followed by the first `depth` components
+/// of the directory containing `path` below it.
+fn group_dir(roots: &[PathBuf], path: &Path, depth: usize) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    // the deepest root holding the file, a file given as root stands for its directory
+    let root = roots
+        .iter()
+        .filter(|root| parent.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map_or(parent, PathBuf::as_path);
+    let below = parent.strip_prefix(root).unwrap_or(parent);
+    let mut dir = root.to_path_buf();
+    dir.extend(below.components().take(depth));
+    match dir.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => dir,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn groups_below_the_walk_root() {
+        let group = |roots: &[&str], path: &str, depth| {
+            let roots: Vec<_> = roots.iter().map(PathBuf::from).collect();
+            group_dir(&roots, Path::new(path), depth)
+        };
+        assert_eq!(group(&["."], "./a/b/c.md", 1), Path::new("./a"));
+        assert_eq!(group(&["."], "./c.md", 1), Path::new("."));
+        assert_eq!(
+            group(&["/notes"], "/notes/a/b/c.md", 1),
+            Path::new("/notes/a")
+        );
+        assert_eq!(
+            group(&["/notes"], "/notes/a/b/c.md", 2),
+            Path::new("/notes/a/b")
+        );
+        assert_eq!(group(&["/notes"], "/notes/c.md", 1), Path::new("/notes"));
+        assert_eq!(
+            group(&["/notes", "/notes/a"], "/notes/a/b/c.md", 1),
+            Path::new("/notes/a/b")
+        );
+        // a file given as root groups under its own directory
+        assert_eq!(
+            group(&["/notes/c.md"], "/notes/c.md", 1),
+            Path::new("/notes")
+        );
+        assert_eq!(group(&[], "<stdin>", 1), Path::new("."));
+    }
+
+    struct Pasted(Option<&'static str>);
+
+    impl Clipboard for Pasted {
+        fn text(&mut self) -> Result<String> {
+            self.0
+                .map(str::to_string)
+                .context("no text in the clipboard")
+        }
+    }
+
+    #[test]
+    fn reads_the_clipboard() {
+        let cli = || {
+            let args = ["utfq", "--clipboard", "--date", "2025-03-10"];
+            Cli::try_parse_from(args).unwrap().resolve().unwrap()
+        };
+        let text = "- [ ] call the plumber <agmd:2025-03-10>\n- [ ] later <agmd:2025-03-11>\n";
+        let mut app = App::with_clipboard(cli(), &mut Pasted(Some(text))).unwrap();
+        let (collected, _) = app.scan();
+        let keys: Vec<_> = collected.keys().collect();
+        assert_eq!(keys, [Path::new(CLIPBOARD_PATH)]);
+        assert_eq!(collected[Path::new(CLIPBOARD_PATH)].len(), 1);
+
+        let err = App::with_clipboard(cli(), &mut Pasted(None)).err().unwrap();
+        assert_eq!(err.to_string(), "no text in the clipboard");
     }
 }
@@ -0,0 +1,73 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use yansi::Paint;
+
+use crate::datemath::{add_days, add_months_clamped, weekday_of};
+
+/// Width of one day cell, the day number followed by its item count.
+const CELL_WIDTH: usize = 7;
+
+/// Open and overdue item counts of a day.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DayCount {
+    pub items: usize,
+    pub overdue: usize,
+}
+
+/// Write `month` as a grid of weeks starting on `week_start`, with the item
+/// count of each day.
+///
+/// Counts are marked `·3`, or `!3` on days with overdue items, and today is
+/// underlined when `color` is on.
+pub fn write_month(
+    w: &mut impl Write,
+    month: NaiveDate,
+    counts: &BTreeMap<NaiveDate, DayCount>,
+    today: NaiveDate,
+    week_start: Weekday,
+    color: bool,
+) -> io::Result<()> {
+    let first = month.with_day(1).unwrap();
+    writeln!(
+        w,
+        "{:^width$}",
+        first.format("%B %Y"),
+        width = CELL_WIDTH * 7
+    )?;
+    let mut weekday = week_start;
+    for _ in 0..7 {
+        let name = &weekday.to_string()[..2];
+        write!(w, "{name:<CELL_WIDTH$}")?;
+        weekday = weekday.succ();
+    }
+    writeln!(w)?;
+
+    let last = add_months_clamped(first, 1)
+        .and_then(|next| add_days(next, -1))
+        .unwrap_or(NaiveDate::MAX);
+    let blanks = weekday_of(first).days_since(week_start) as usize;
+    write!(w, "{:width$}", "", width = CELL_WIDTH * blanks)?;
+    for day in first.iter_days().take_while(|&day| day <= last) {
+        let count = counts.get(&day).copied().unwrap_or_default();
+        let tag = match count {
+            DayCount { items: 0, .. } => String::new(),
+            DayCount { overdue: 0, items } => format!("·{items}"),
+            DayCount { items, .. } => format!("!{items}"),
+        };
+        let cell = format!("{:>2}{tag:<width$}", day.day(), width = CELL_WIDTH - 2);
+        match color {
+            true if day == today => write!(w, "{}", cell.bold().underline())?,
+            true if count.overdue > 0 => write!(w, "{}", cell.red())?,
+            true if count.items > 0 => write!(w, "{}", cell.yellow())?,
+            _ => write!(w, "{cell}")?,
+        }
+        if weekday_of(day).succ() == week_start {
+            writeln!(w)?;
+        }
+    }
+    writeln!(w)
+}
@@ -1,32 +1,1066 @@
-use chrono::{Duration, Local, NaiveDate};
-use clap::Parser;
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 
-#[derive(Parser, Debug)]
+use chrono::{NaiveDate, Weekday};
+use clap::{ArgAction, CommandFactory, Parser, ValueEnum, error::ErrorKind};
+use regex::{Regex, RegexBuilder};
+use url::Url;
+
+use crate::{
+    datemath::{self, add_days},
+    output, walk,
+};
+
+/// Options with a default from an environment variable, read in [`Cli::resolve`].
+///
+/// clap's own env support counts those values as given on the command line,
+/// which would make them conflict with other output modes.
+pub const ENV_OPTIONS: [(&str, &str); 2] = [("color", "UTFQ_COLOR"), ("format", "UTFQ_FORMAT")];
+
+/// Options that make no sense together, each with the options it rejects.
+///
+/// Kept as data so every new option declares its interactions in one place,
+/// [`command`] turns them into clap rules and a test tries each pair.
+const CONFLICTS: &[(&str, &[&str])] = &[
+    ("only_someday", &["all", "someday", "forecast"]),
+    (
+        "malformed",
+        &[
+            "date_flag",
+            "all",
+            "only_someday",
+            "forecast",
+            "overdue",
+            "min_overdue",
+        ],
+    ),
+    // --min-overdue implies --overdue
+    (
+        "overdue",
+        &[
+            "date_flag",
+            "all",
+            "someday",
+            "only_someday",
+            "forecast",
+            "sort",
+        ],
+    ),
+    (
+        "min_overdue",
+        &[
+            "date_flag",
+            "all",
+            "someday",
+            "only_someday",
+            "forecast",
+            "sort",
+        ],
+    ),
+    ("forecast", &["all"]),
+    (
+        "calendar",
+        &[
+            "count",
+            "stats",
+            "format",
+            "forecast",
+            "overdue",
+            "min_overdue",
+            "malformed",
+            "only_someday",
+            "limit",
+            "limit_per_file",
+        ],
+    ),
+    (
+        "week",
+        &[
+            "count",
+            "stats",
+            "calendar",
+            "format",
+            "forecast",
+            "overdue",
+            "min_overdue",
+            "malformed",
+            "only_someday",
+            "limit",
+            "limit_per_file",
+        ],
+    ),
+    (
+        "template",
+        &["count", "stats", "calendar", "week", "format"],
+    ),
+    (
+        "stats",
+        &[
+            "date_flag",
+            "count",
+            "forecast",
+            "overdue",
+            "min_overdue",
+            "malformed",
+            "only_someday",
+            "limit",
+            "limit_per_file",
+            "out",
+        ],
+    ),
+    ("count", &["format"]),
+    ("tui", &["format"]),
+    (
+        "watch",
+        &["count", "stats", "calendar", "week", "template", "format"],
+    ),
+    ("quiet", &["verbose"]),
+    ("no_config", &["profile", "profiles", "view", "views"]),
+];
+
+/// About a hundred years, anything beyond is almost certainly a typo.
+const MAX_RELATIVE_DAYS: i64 = 36525;
+
+const DATE_LONG_HELP: &str = "\
+Which date to show, defaults to today
+
+Accepted forms:
+  YYYY-MM-DD  an absolute date
+  N           a day offset relative to today, may be negative
+
+Examples:
+  utfq              today
+  utfq 2025-12-01   a fixed date
+  utfq 1            tomorrow
+  utfq -1           yesterday
+  utfq --date 7     one week from today";
+
+const TARGETS_LONG_HELP: &str = "\
+Which date to show, and the directory or markdown file to scan
+
+An argument naming an existing path is always a PATH, otherwise it is
+read as a DATE. At most one DATE but several PATHs may be given. The
+date defaults to today and the path to UTFQ_ROOT, a list of paths like
+PATH, then to the `root` of the config file, or else the current
+directory.
+
+A PATH of `-` reads a single markdown file from stdin, and `@NAME`
+that is not a path uses the view NAME like --view.
+
+DATE forms:
+  YYYY-MM-DD  an absolute date
+  N           a day offset relative to today, may be negative
+
+Examples:
+  utfq                   today in the current directory
+  utfq 2025-12-01        a fixed date
+  utfq -1 notes          yesterday in notes/
+  utfq notes/todo.md 1   tomorrow in a single file
+  utfq notes journal     today in both notes/ and journal/
+  cat todo.md | utfq -   today in markdown from stdin
+  utfq @review notes     the view `review` of the config file in notes/
+  utfq --date 3 3        three days ahead in a directory named 3";
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, args_override_self = true)]
 pub struct Cli {
-    /// Which date to show
+    /// Date to show and paths to scan, in any order
     #[arg(
+        value_name = "DATE|PATH",
+        allow_negative_numbers = true,
+        long_help = TARGETS_LONG_HELP
+    )]
+    targets: Vec<String>,
+    /// Which date to show, for when it could be mistaken for a path
+    #[arg(
+        long = "date",
+        value_name = "DATE",
         allow_hyphen_values = true,
         value_parser = parse_date_arg,
-        default_value_t = Local::now().date_naive()
+        long_help = DATE_LONG_HELP
     )]
-    pub date: NaiveDate,
+    date_flag: Option<NaiveDate>,
+    /// Date resolved from the positional arguments.
+    #[arg(skip)]
+    date: Option<NaiveDate>,
+    /// Directories or single markdown files to scan.
+    #[arg(skip = vec![PathBuf::from(".")])]
+    pub paths: Vec<PathBuf>,
+    /// Whether `-` asked to read markdown from stdin.
+    #[arg(skip)]
+    pub stdin: bool,
+    /// Read markdown from the system clipboard instead of the paths
+    #[arg(long, default_value_t = false)]
+    pub clipboard: bool,
     /// List all agmd items
     #[arg(short, long, default_value_t = false)]
     pub all: bool,
     /// List done items
     #[arg(short, long, default_value_t = false)]
     pub done: bool,
+    /// Also list someday items, marked with a value-less `<agmd:>`
+    #[arg(long, default_value_t = false)]
+    pub someday: bool,
+    /// List only someday items
+    #[arg(long, default_value_t = false)]
+    pub only_someday: bool,
+    /// List only items whose markers hold no date, with the reason
+    #[arg(long, default_value_t = false)]
+    pub malformed: bool,
+    /// List only open items dated before today, oldest first
+    #[arg(long, default_value_t = false)]
+    pub overdue: bool,
+    /// Only scan files matching GLOB relative to their root, may be repeated
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    pub include: Vec<String>,
+    /// Skip files matching GLOB relative to their root, wins over --include
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    pub exclude: Vec<String>,
+    /// Take dates of unannotated tasks from a following `<!-- due: YYYY-MM-DD -->` comment
+    #[arg(long, default_value_t = false)]
+    pub html_comment_dates: bool,
+    /// Only list items tagged `#TAG`, may be repeated to require all of them
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+    /// Require any instead of all of the --tag tags
+    #[arg(long, default_value_t = false, requires = "tag")]
+    pub any_tag: bool,
+    /// Only list items mentioning `@NAME`, may be repeated
+    #[arg(long, value_name = "NAME")]
+    pub assignee: Vec<String>,
+    /// Count `@VARIANT` as `@NAME` when filtering and grouping, may be repeated
+    #[arg(long, value_name = "VARIANT=NAME", value_parser = parse_alias)]
+    pub alias: Vec<(String, String)>,
+    /// Only list open items overdue by at least DAYS, implies --overdue
+    #[arg(long, value_name = "DAYS")]
+    pub min_overdue: Option<i64>,
+    /// Upper bounds in days of the overdue buckets, in increasing order
+    #[arg(
+        long,
+        value_name = "DAYS",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value = "3,7,30,90"
+    )]
+    pub overdue_buckets: Vec<u64>,
+    /// Follow each item with its date relative to today, like `(due in 3d)`
+    #[arg(long, default_value_t = false)]
+    pub relative: bool,
+    /// Do not follow each item with the headings it is under
+    #[arg(long, default_value_t = false)]
+    pub no_context: bool,
+    /// Show at most N items in total, those with the earliest dates
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+    /// Show at most N items from each file
+    #[arg(long, value_name = "N")]
+    pub limit_per_file: Option<usize>,
+    /// Only list items matching the regex, may be repeated to match any of them
+    #[arg(long, value_name = "PATTERN", value_parser = parse_regex)]
+    pub grep: Vec<String>,
+    /// Make --grep case insensitive
+    #[arg(short = 'i', long, default_value_t = false)]
+    pub ignore_case: bool,
+    /// List items not matching --grep instead
+    #[arg(long, default_value_t = false, requires = "grep")]
+    pub invert_grep: bool,
+    /// Link the shown date to a calendar, `{y}`, `{m}` and `{d}` are replaced by the date
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_calendar_url)]
+    pub calendar_url: Option<String>,
+    /// When to color items by urgency, `auto` only on a terminal without NO_COLOR [env: UTFQ_COLOR]
+    #[arg(long = "color", value_enum, value_name = "WHEN")]
+    color_flag: Option<ColorChoice>,
+    /// Color choice resolved from --color, UTFQ_COLOR or the default.
+    #[arg(skip = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// When to emit hyperlinks, `auto` only when stdout is a terminal
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = HyperlinkChoice::Auto)]
+    pub hyperlinks: HyperlinkChoice,
+    /// URL scheme of the per-item hyperlinks to their source line
+    #[arg(long, value_enum, value_name = "SCHEME", default_value_t = LinkScheme::File)]
+    pub link_scheme: LinkScheme,
+    /// Print a month grid with the item count of each day, the viewed month or `=YYYY-MM`
+    #[arg(
+        long,
+        value_name = "YYYY-MM",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = parse_month
+    )]
+    pub calendar: Option<Option<NaiveDate>>,
+    /// Print the items of each day of the viewed week, or of N weeks later with `=N`
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0"
+    )]
+    pub week: Option<i64>,
+    /// First day of the week in the --week agenda and the --calendar grid
+    #[arg(long, value_enum, value_name = "DAY", default_value_t = WeekStart::Monday)]
+    pub week_start: WeekStart,
+    /// Print each item as TEMPLATE, with placeholders like `{due}` and escapes like `\t`
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_template)]
+    pub template: Option<String>,
+    /// Print totals, overdue and upcoming numbers over all dates, as JSON with --format json
+    #[arg(long, default_value_t = false)]
+    pub stats: bool,
+    /// Print per-file and total item counts instead of the items
+    #[arg(long, default_value_t = false)]
+    pub count: bool,
+    /// Exit with code 1 when an open item is overdue, whatever date is viewed
+    #[arg(long, default_value_t = false)]
+    pub fail_if_overdue: bool,
+    /// Exit with code 1 when any item is listed
+    #[arg(long, default_value_t = false)]
+    pub fail_if_any: bool,
+    /// Warn about checked items completed early and dates too far from today to be meant
+    #[arg(long, default_value_t = false)]
+    pub pedantic: bool,
+    /// Days ahead of the viewed date past which --pedantic calls a checked item early
+    #[arg(long, value_name = "DAYS", default_value_t = 7)]
+    pub done_early_days: i64,
+    /// Years from today past which --pedantic calls a date a probable typo
+    #[arg(long, value_name = "YEARS", default_value_t = 20)]
+    pub date_horizon_years: u32,
+    /// After the output, ask whether to uncheck each item completed early, implies --pedantic
+    #[arg(long, default_value_t = false)]
+    pub review_early: bool,
+    /// Log walked roots, skipped files and filtered items to stderr, repeat for more detail
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Only log errors and leave out the --forecast banner and notes
+    #[arg(short, long, default_value_t = false)]
+    pub quiet: bool,
+    /// Ignore `utfq.toml` and the user config file
+    #[arg(long, default_value_t = false)]
+    pub no_config: bool,
+    /// Use the options saved as `[profile.NAME]` in the config file [env: UTFQ_PROFILE]
+    ///
+    /// A profile sits between the rest of the file and the environment, and its `root` key
+    /// gives the paths to scan when neither PATH nor UTFQ_ROOT does.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Print the profiles of the config file with their roots, then exit
+    #[arg(long, default_value_t = false)]
+    pub profiles: bool,
+    /// Each profile of the config file with its roots, for --profiles.
+    #[arg(skip)]
+    pub profile_roots: Vec<(String, Vec<PathBuf>)>,
+    /// Paths from the `root` key of the config file, used without PATH or UTFQ_ROOT.
+    #[arg(skip)]
+    pub config_roots: Vec<PathBuf>,
+    /// Use the options saved as `[view.NAME]` in the config file, `@NAME` is short for it
+    ///
+    /// Options given on the command line still win, so `utfq @today --done` works.
+    #[arg(long, value_name = "NAME")]
+    pub view: Option<String>,
+    /// Print the views of the config file with the options they stand for, then exit
+    #[arg(long, default_value_t = false)]
+    pub views: bool,
+    /// Each view of the config file with its options, for --views.
+    #[arg(skip)]
+    pub view_options: Vec<(String, String)>,
+    /// Print version, commit and build details, then exit
+    #[arg(long, default_value_t = false)]
+    pub build_info: bool,
+    /// Print items in a machine format or as text instead of running the TUI [env: UTFQ_FORMAT]
+    ///
+    /// UTFQ_FORMAT only applies when no other output mode like --count or --tui is given.
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+    /// Run the TUI even when UTFQ_FORMAT is set
+    #[arg(long, default_value_t = false)]
+    pub tui: bool,
+    /// With --format ics, write one file per day and an index.ics into DIR
+    #[arg(long, value_name = "DIR", requires = "format")]
+    pub out: Option<PathBuf>,
+    /// Omit the header row of --format csv
+    #[arg(long, default_value_t = false)]
+    pub no_header: bool,
+    /// Group items by `file`, by the first N directory components with `dir[:N]`, by `date`,
+    /// by `overdue` bucket or by `assignee`
+    ///
+    /// With `date`, each item is listed once, under the date of its first marker, and items
+    /// without a date come last. With `overdue`, the longest overdue bucket comes first.
+    /// With `assignee`, an item is listed under each name it mentions, after --alias.
+    #[arg(long, value_name = "GROUP", value_parser = parse_group_by, default_value = "file")]
+    pub group_by: GroupBy,
+    /// When the date has no items, widen to the following days until some show up
+    #[arg(long, default_value_t = false)]
+    pub forecast: bool,
+    /// Number of days to widen to for each --forecast step
+    #[arg(
+        long,
+        value_name = "DAYS",
+        value_delimiter = ',',
+        default_value = "7,30"
+    )]
+    pub forecast_steps: Vec<u64>,
+    /// After the output, print the slowest files to parse to stderr, 10 or `=N`
+    ///
+    /// `--stats --format json` always lists the slowest files, as many as given here.
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "10"
+    )]
+    pub slow_files: Option<usize>,
+    /// Order of the file sections, or of all items in a flat list for `date` and `text`
+    #[arg(long, value_enum, default_value_t = Sort::Path)]
+    pub sort: Sort,
+    /// Treat paths differing only in case as the same file, detected per root otherwise
+    #[arg(long, default_value_t = false)]
+    pub case_insensitive_paths: bool,
+    /// Reload the TUI when markdown or ignore files under the roots change
+    #[arg(long, default_value_t = false)]
+    pub watch: bool,
+    /// Show when each file was last modified
+    #[arg(long, default_value_t = false)]
+    pub show_mtime: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One section per file.
+    File,
+    /// One section per directory, truncated to the given depth.
+    Dir(usize),
+    /// One section per day, each item under the date of its first marker.
+    Date,
+    /// One section per --overdue-buckets bucket, the oldest first.
+    Overdue,
+    /// One section per `@mention`, unassigned items last.
+    Assignee,
+}
+
+/// Whether the options `a` and `b` conflict, in either order.
+pub fn conflicts(a: &str, b: &str) -> bool {
+    CONFLICTS
+        .iter()
+        .any(|&(id, others)| (id == a && others.contains(&b)) || (id == b && others.contains(&a)))
+}
+
+/// The clap command of [`Cli`], with the rules of [`CONFLICTS`].
+pub fn command() -> clap::Command {
+    CONFLICTS.iter().fold(Cli::command(), |cmd, &(id, others)| {
+        cmd.mut_arg(id, |arg| arg.conflicts_with_all(others))
+    })
+}
+
+impl Cli {
+    /// The name `mention` stands for, following --alias.
+    pub fn canonical_name<'a>(&'a self, mention: &'a str) -> &'a str {
+        let mention = mention.trim_start_matches('@');
+        self.alias
+            .iter()
+            .find(|(variant, _)| variant.eq_ignore_ascii_case(mention))
+            .map_or(mention, |(_, name)| name)
+    }
+
+    /// The --grep patterns combined into one regex.
+    pub fn grep_regex(&self) -> Option<Regex> {
+        if self.grep.is_empty() {
+            return None;
+        }
+        let alternatives: Vec<_> = self.grep.iter().map(|p| format!("(?:{p})")).collect();
+        let regex = RegexBuilder::new(&alternatives.join("|"))
+            .case_insensitive(self.ignore_case)
+            .build()
+            .expect("patterns are validated while parsing");
+        Some(regex)
+    }
+
+    /// The first positional argument naming an existing path, before [`Cli::resolve`].
+    pub fn root(&self) -> PathBuf {
+        self.targets
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.exists())
+            .or_else(|| env::split_paths(&env::var_os("UTFQ_ROOT")?).next())
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// The view given by --view or as `@NAME`, before [`Cli::resolve`].
+    pub fn view_name(&self) -> Option<&str> {
+        let shorthand = (self.targets.iter())
+            .filter(|target| !Path::new(target).exists())
+            .find_map(|target| target.strip_prefix('@'));
+        self.view.as_deref().or(shorthand)
+    }
+
+    /// Sort the positional arguments into the date and the path.
+    ///
+    /// An existing path always wins over reading the argument as a date.
+    pub fn resolve(mut self) -> Result<Self, clap::Error> {
+        let mut cmd = command();
+        let mut paths = vec![];
+        // flags that look at every date
+        let dateless = [
+            (self.overdue, "--overdue"),
+            (self.min_overdue.is_some(), "--min-overdue"),
+            (self.malformed, "--malformed"),
+            (self.stats, "--stats"),
+        ]
+        .into_iter()
+        .find_map(|(set, flag)| set.then_some(flag));
+
+        for target in std::mem::take(&mut self.targets) {
+            if target == "-" {
+                self.stdin = true;
+                continue;
+            }
+            let path = PathBuf::from(&target);
+            // read by the config file already
+            if let Some(name) = target.strip_prefix('@')
+                && !path.exists()
+            {
+                if self.view.as_ref().is_some_and(|view| view != name) {
+                    return Err(cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        format!("more than one view given at `{target}`"),
+                    ));
+                }
+                self.view = Some(name.to_string());
+                continue;
+            }
+            let date = parse_date_arg(&target);
+            if path.exists() {
+                if date.is_ok() && !self.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "note: `{target}` is an existing path, use --date {target} for the date"
+                    );
+                }
+                paths.push(path);
+                continue;
+            }
+            if let (Ok(_), Some(flag)) = (&date, dateless) {
+                return Err(cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    format!("the date `{target}` cannot be used with {flag}"),
+                ));
+            }
+            match date {
+                Ok(date) if self.date.is_none() && self.date_flag.is_none() => {
+                    self.date = Some(date)
+                }
+                Ok(_) => {
+                    return Err(cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        format!("more than one date given at `{target}`, use --date for the date"),
+                    ));
+                }
+                Err(err) => {
+                    return Err(cmd.error(
+                        ErrorKind::ValueValidation,
+                        format!("`{target}` is neither an existing path nor a date: {err}"),
+                    ));
+                }
+            }
+        }
+
+        if self.stdin && self.clipboard {
+            return Err(cmd.error(
+                ErrorKind::ArgumentConflict,
+                "`-` and --clipboard cannot be used together",
+            ));
+        }
+        // pasted markdown stands in for the default paths
+        let pasted = self.stdin || self.clipboard;
+        if paths.is_empty()
+            && !pasted
+            && let Some(root) = env::var_os("UTFQ_ROOT").filter(|v| !v.is_empty())
+        {
+            for path in env::split_paths(&root).filter(|p| !p.as_os_str().is_empty()) {
+                if !path.exists() {
+                    return Err(cmd.error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "`{}` in the environment variable UTFQ_ROOT is not an existing path",
+                            path.display()
+                        ),
+                    ));
+                }
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() && !pasted {
+            paths = std::mem::take(&mut self.config_roots);
+        }
+        if !paths.is_empty() || pasted {
+            self.paths = paths;
+        }
+        self.color = match self.color_flag {
+            Some(color) => color,
+            None => env_value(&mut cmd, "UTFQ_COLOR")?.unwrap_or(ColorChoice::Auto),
+        };
+        let other_mode = self.count
+            || self.stats
+            || self.calendar.is_some()
+            || self.week.is_some()
+            || self.template.is_some()
+            || self.watch
+            || self.tui;
+        if self.format.is_none() && !other_mode {
+            self.format = env_value(&mut cmd, "UTFQ_FORMAT")?;
+        }
+
+        if !self.overdue_buckets.is_sorted_by(|a, b| a < b) {
+            return Err(cmd.error(
+                ErrorKind::ValueValidation,
+                "--overdue-buckets must be in increasing order",
+            ));
+        }
+        if self.min_overdue.is_some() {
+            self.overdue = true;
+        }
+        if self.overdue {
+            self.sort = Sort::Date;
+        }
+        if self.review_early {
+            if self.stdin {
+                return Err(cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "--review-early reads the answers from stdin, it cannot be used with `-`",
+                ));
+            }
+            self.pedantic = true;
+        }
+        if self.stats || self.calendar.is_some() || self.week.is_some() {
+            self.all = true;
+        }
+
+        Ok(self)
+    }
+
+    /// The date given either positionally or by `--date`, today if neither.
+    pub fn date(&self) -> NaiveDate {
+        self.date.or(self.date_flag).unwrap_or_else(datemath::today)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is not set.
+    Auto,
+    /// Always color.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HyperlinkChoice {
+    /// Hyperlinks when stdout is a terminal.
+    Auto,
+    /// Always emit hyperlinks.
+    Always,
+    /// Never emit hyperlinks.
+    Never,
+}
+
+impl HyperlinkChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            HyperlinkChoice::Auto => io::stdout().is_terminal(),
+            HyperlinkChoice::Always => true,
+            HyperlinkChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinkScheme {
+    /// `file://` URLs with a `#L42` fragment.
+    File,
+    /// `vscode://file/...:42` URLs opening the line in VS Code.
+    Vscode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A JSON array of items.
+    Json,
+    /// An iCalendar document with one VTODO per item.
+    Ics,
+    /// CSV rows of file, line, done, date and text.
+    Csv,
+    /// todo.txt lines with `due:` tags and the file as `+project`.
+    Todotxt,
+    /// The lines of the TUI as plain text.
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WeekStart {
+    /// Weeks run Monday to Sunday.
+    Monday,
+    /// Weeks run Sunday to Saturday.
+    Sunday,
+}
+
+impl WeekStart {
+    pub fn weekday(self) -> Weekday {
+        match self {
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Sort {
+    /// Lexicographic path order.
+    Path,
+    /// Most recently modified file first.
+    FileMtime,
+    /// Earliest item date first, undated items last.
+    Date,
+    /// Alphabetical by task text.
+    Text,
+}
+
+/// The value of the environment variable `var`, if set and not empty.
+fn env_value<T: ValueEnum>(cmd: &mut clap::Command, var: &str) -> Result<Option<T>, clap::Error> {
+    let Some(value) = env::var(var).ok().filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+    match T::from_str(&value, true) {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => {
+            let expected: Vec<_> = T::value_variants()
+                .iter()
+                .filter_map(|v| v.to_possible_value())
+                .map(|v| v.get_name().to_string())
+                .collect();
+            Err(cmd.error(
+                ErrorKind::ValueValidation,
+                format!(
+                    "invalid value `{value}` in the environment variable {var}, expect one of {}",
+                    expected.join(", ")
+                ),
+            ))
+        }
+    }
 }
 
 fn parse_date_arg(s: &str) -> Result<NaiveDate, String> {
     match s.parse::<i64>() {
+        Ok(relative) if !(-MAX_RELATIVE_DAYS..=MAX_RELATIVE_DAYS).contains(&relative) => {
+            Err(format!(
+                "relative offset out of range, expect at most {MAX_RELATIVE_DAYS} days either way"
+            ))
+        }
         Ok(relative) => {
-            let today = Local::now().date_naive();
-            today
-                .checked_add_signed(Duration::days(relative))
-                .ok_or_else(|| "date out of range".to_string())
+            add_days(datemath::today(), relative).ok_or_else(|| "date out of range".to_string())
         }
         Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
             .map_err(|_| "expect YYYY-MM-DD or relative".to_string()),
     }
 }
+
+/// The first day of a `YYYY-MM` month.
+fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+        .map_err(|_| "expect YYYY-MM".to_string())
+}
+
+/// Unescape `\t`, `\n` and `\\`, and check the placeholders are known.
+fn parse_template(s: &str) -> Result<String, String> {
+    let mut template = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => template.push('\t'),
+            ('\\', Some('n')) => template.push('\n'),
+            ('\\', Some('\\')) => template.push('\\'),
+            _ => {
+                template.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+
+    let fields = output::TEMPLATE_FIELDS;
+    if let Some(unknown) = output::template_fields(&template)
+        .into_iter()
+        .find(|field| !fields.contains(field))
+    {
+        let expected: Vec<_> = fields.iter().map(|f| format!("{{{f}}}")).collect();
+        return Err(format!(
+            "unknown placeholder {{{unknown}}}, expect one of {}",
+            expected.join(", ")
+        ));
+    }
+    Ok(template)
+}
+
+fn parse_glob(s: &str) -> Result<String, String> {
+    walk::build_overrides(Path::new("."), &[s.to_string()], &[]).map_err(|err| err.to_string())?;
+    Ok(s.to_string())
+}
+
+fn parse_regex(s: &str) -> Result<String, String> {
+    Regex::new(s).map_err(|err| err.to_string())?;
+    Ok(s.to_string())
+}
+
+fn parse_group_by(s: &str) -> Result<GroupBy, String> {
+    match s.split_once(':') {
+        None if s == "file" => Ok(GroupBy::File),
+        None if s == "dir" => Ok(GroupBy::Dir(1)),
+        None if s == "date" => Ok(GroupBy::Date),
+        None if s == "overdue" => Ok(GroupBy::Overdue),
+        None if s == "assignee" => Ok(GroupBy::Assignee),
+        Some(("dir", depth)) => match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => Ok(GroupBy::Dir(depth)),
+            _ => Err("expect a positive depth after `dir:`".to_string()),
+        },
+        _ => Err("expect `file`, `dir`, `dir:N`, `date`, `overdue` or `assignee`".to_string()),
+    }
+}
+
+fn parse_alias(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((variant, name)) if !variant.is_empty() && !name.is_empty() => Ok((
+            variant.trim_start_matches('@').to_string(),
+            name.trim_start_matches('@').to_string(),
+        )),
+        _ => Err("expect VARIANT=NAME".to_string()),
+    }
+}
+
+/// Version and build details for bug reports.
+pub fn build_info() -> String {
+    let profile = match cfg!(debug_assertions) {
+        true => "debug",
+        false => "release",
+    };
+    format!(
+        "utfq {}\ncommit: {}\ntarget: {}\nprofile: {profile}",
+        env!("CARGO_PKG_VERSION"),
+        env!("UTFQ_GIT_COMMIT"),
+        env!("UTFQ_TARGET"),
+    )
+}
+
+/// Fill the `{y}`, `{m}` and `{d}` placeholders of a calendar url template.
+pub fn fill_calendar_url(template: &str, date: NaiveDate) -> String {
+    template
+        .replace("{y}", &date.format("%Y").to_string())
+        .replace("{m}", &date.format("%m").to_string())
+        .replace("{d}", &date.format("%d").to_string())
+}
+
+fn parse_calendar_url(s: &str) -> Result<String, String> {
+    let example = fill_calendar_url(s, NaiveDate::default());
+    Url::parse(&example).map_err(|err| format!("invalid url {example}: {err}"))?;
+    Ok(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::FromArgMatches;
+
+    use super::*;
+
+    fn resolve(args: &[&str]) -> Result<Cli, clap::Error> {
+        let args = std::iter::once("utfq").chain(args.iter().copied());
+        Cli::from_arg_matches(&command().try_get_matches_from(args)?)?.resolve()
+    }
+
+    /// The option `id` as it would be given, with a valid value if it takes one.
+    fn sample(cmd: &clap::Command, id: &str) -> String {
+        let arg = cmd.get_arguments().find(|arg| arg.get_id() == id).unwrap();
+        let flag = format!("--{}", arg.get_long().unwrap());
+        let value = match id {
+            "date_flag" => "2025-03-10",
+            "format" => "json",
+            "template" => "{file}",
+            "sort" => "date",
+            "out" => "out",
+            "view" | "profile" => "work",
+            "limit" | "limit_per_file" | "min_overdue" => "1",
+            _ => return flag,
+        };
+        format!("{flag}={value}")
+    }
+
+    fn existing_dir() -> String {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/src").to_string()
+    }
+
+    #[test]
+    fn date_and_path_in_any_order() {
+        let dir = existing_dir();
+        let date = NaiveDate::from_ymd_opt(2025, 12, 1).unwrap();
+        for args in [["2025-12-01", dir.as_str()], [dir.as_str(), "2025-12-01"]] {
+            let cli = resolve(&args).unwrap();
+            assert_eq!(cli.date(), date);
+            assert_eq!(cli.paths, [PathBuf::from(&dir)]);
+        }
+    }
+
+    #[test]
+    fn flags_after_positionals_stay_flags() {
+        let dir = existing_dir();
+        let cli = resolve(&[&dir, "-a"]).unwrap();
+        assert!(cli.all);
+        assert_eq!(cli.paths, [PathBuf::from(&dir)]);
+
+        let cli = resolve(&["-1", "--done"]).unwrap();
+        assert!(cli.done);
+        assert_eq!(cli.date(), add_days(datemath::today(), -1).unwrap());
+    }
+
+    #[test]
+    fn several_paths() {
+        let dir = existing_dir();
+        let cargo = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let cli = resolve(&[&dir, cargo]).unwrap();
+        assert_eq!(cli.paths, [PathBuf::from(&dir), PathBuf::from(cargo)]);
+    }
+
+    #[test]
+    fn defaults_to_today_and_current_dir() {
+        let cli = resolve(&[]).unwrap();
+        assert_eq!(cli.date(), datemath::today());
+        assert_eq!(cli.paths, [PathBuf::from(".")]);
+    }
+
+    #[test]
+    fn dash_reads_stdin() {
+        let cli = resolve(&["-"]).unwrap();
+        assert!(cli.stdin);
+        assert!(cli.paths.is_empty());
+    }
+
+    #[test]
+    fn clipboard_replaces_the_paths() {
+        let cli = resolve(&["--clipboard"]).unwrap();
+        assert!(cli.paths.is_empty());
+        let err = resolve(&["--clipboard", "-"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn review_early_needs_stdin() {
+        let cli = resolve(&["--review-early"]).unwrap();
+        assert!(cli.pedantic);
+        let err = resolve(&["--review-early", "-"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn min_overdue_implies_overdue() {
+        let cli = resolve(&["--min-overdue", "30"]).unwrap();
+        assert!(cli.overdue);
+        let err = resolve(&["--min-overdue", "30", "2025-03-10"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn overdue_buckets_increase() {
+        let cli = resolve(&["--overdue-buckets", "1,14"]).unwrap();
+        assert_eq!(cli.overdue_buckets, [1, 14]);
+        let err = resolve(&["--overdue-buckets", "14,1"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn every_conflict_is_rejected() {
+        let cmd = command();
+        for &(id, others) in CONFLICTS {
+            for &other in others {
+                let args = ["utfq".to_string(), sample(&cmd, id), sample(&cmd, other)];
+                let err = command().try_get_matches_from(&args).unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::ArgumentConflict, "{args:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn two_dates_conflict() {
+        let err = resolve(&["1", "2"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        let err = resolve(&["--date", "1", "2"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn neither_path_nor_date() {
+        let err = resolve(&["no/such/dir"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn optional_values_need_equals() {
+        let dir = existing_dir();
+        let cli = resolve(&["--week", &dir]).unwrap();
+        assert_eq!(cli.week, Some(0));
+        assert_eq!(cli.paths, [PathBuf::from(&dir)]);
+
+        let cli = resolve(&["--week", "-a"]).unwrap();
+        assert_eq!(cli.week, Some(0));
+        assert!(cli.all);
+
+        let cli = resolve(&["--week=-1", "--slow-files", &dir]).unwrap();
+        assert_eq!(cli.week, Some(-1));
+        assert_eq!(cli.slow_files, Some(10));
+        assert_eq!(cli.paths, [PathBuf::from(&dir)]);
+
+        let cli = resolve(&["--calendar", &dir]).unwrap();
+        assert_eq!(cli.calendar, Some(None));
+        let cli = resolve(&["--calendar=2025-03"]).unwrap();
+        assert_eq!(cli.calendar, Some(NaiveDate::from_ymd_opt(2025, 3, 1)));
+    }
+
+    #[test]
+    fn absurd_offsets_are_errors() {
+        // these used to overflow the date arithmetic and panic
+        for arg in [
+            "999999999",
+            "-999999999",
+            "9223372036854775807",
+            "-9223372036854775808",
+        ] {
+            assert!(parse_date_arg(arg).is_err(), "{arg}");
+        }
+        // beyond i64, not a number nor a date
+        assert!(parse_date_arg("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn offsets_up_to_a_hundred_years() {
+        let today = datemath::today();
+        for days in [36500, -36500, MAX_RELATIVE_DAYS, -MAX_RELATIVE_DAYS] {
+            assert_eq!(
+                parse_date_arg(&days.to_string()),
+                Ok(add_days(today, days).unwrap())
+            );
+        }
+        for days in [MAX_RELATIVE_DAYS + 1, -MAX_RELATIVE_DAYS - 1] {
+            assert!(parse_date_arg(&days.to_string()).is_err(), "{days}");
+        }
+    }
+}
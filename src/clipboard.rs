@@ -0,0 +1,31 @@
+//! Markdown pasted into the clipboard, read with --clipboard.
+
+use color_eyre::Result;
+#[cfg(feature = "clipboard")]
+use color_eyre::eyre::Context;
+#[cfg(not(feature = "clipboard"))]
+use color_eyre::eyre::bail;
+
+/// Where --clipboard reads its text from.
+pub trait Clipboard {
+    fn text(&mut self) -> Result<String>;
+}
+
+/// The system clipboard, readable when built with the `clipboard` feature.
+pub struct System;
+
+impl Clipboard for System {
+    #[cfg(feature = "clipboard")]
+    fn text(&mut self) -> Result<String> {
+        // fails without a display, like over ssh
+        let mut clipboard = arboard::Clipboard::new().wrap_err("cannot open the clipboard")?;
+        clipboard
+            .get_text()
+            .wrap_err("cannot read text from the clipboard")
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn text(&mut self) -> Result<String> {
+        bail!("--clipboard needs utfq built with the `clipboard` feature")
+    }
+}
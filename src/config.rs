@@ -0,0 +1,521 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgAction, ArgMatches, FromArgMatches, error::ErrorKind, parser::ValueSource};
+use color_eyre::{
+    Result,
+    eyre::{Context, bail},
+};
+use toml::{Table, Value};
+
+use crate::cli::{self, Cli, ENV_OPTIONS};
+
+/// Config file looked up in the scan root.
+const FILE_NAME: &str = "utfq.toml";
+
+/// Parse the command line on top of the defaults from the config file.
+///
+/// The file holds long option names as keys, like `sort = "date"` or
+/// `relative = true`, and its values become the defaults of those options.
+/// An `[aliases]` table maps `@mention` variants to names, like --alias.
+/// A `[view.NAME]` table holds more of those keys, used on top of the
+/// others with --view NAME.
+/// Options given on the command line, lists included, replace them, and so
+/// does a set environment variable. A value is left out when it conflicts
+/// with an option given on the command line, like `format` with `--count`.
+pub fn parse_cli() -> Result<Cli> {
+    let given = cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&given).unwrap_or_else(|err| err.exit());
+    let path = match cli.no_config {
+        true => None,
+        false => find(&cli.root()),
+    };
+    let profile =
+        (cli.profile.clone()).or_else(|| env::var("UTFQ_PROFILE").ok().filter(|v| !v.is_empty()));
+    let cli = match path {
+        None => {
+            let wanted = [("profile", profile.as_deref()), ("view", cli.view_name())];
+            if let Some((kind, name)) = wanted
+                .into_iter()
+                .find_map(|(kind, name)| Some((kind, name?)))
+            {
+                match cli.no_config {
+                    true => bail!("unknown {kind} `{name}` with --no-config"),
+                    false => bail!("unknown {kind} `{name}`, there is no config file"),
+                }
+            }
+            cli
+        }
+        Some(path) => {
+            let text = fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read config {}", path.display()))?;
+            let config = load(&path, &text, profile.as_deref(), cli.view_name())?;
+            let cmd = with_defaults(cli::command(), &path, &config.table, &given)?;
+            let mut cli =
+                Cli::from_arg_matches(&cmd.get_matches()).unwrap_or_else(|err| err.exit());
+            cli.config_roots = config.roots;
+            cli.view_options = (config.views.iter())
+                .map(|(name, view)| (name.clone(), expansion(view)))
+                .collect();
+            for (name, mut profile) in config.profiles {
+                let roots = take_roots(&path, &mut profile)?;
+                cli.profile_roots.push((name, roots));
+            }
+            cli
+        }
+    };
+    Ok(cli.resolve().unwrap_or_else(|err| err.exit()))
+}
+
+/// `utfq.toml` in `root`, or else `$XDG_CONFIG_HOME/utfq/config.toml`.
+fn find(root: &Path) -> Option<PathBuf> {
+    let local = root.join(FILE_NAME);
+    if local.is_file() {
+        return Some(local);
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    let global = config_home.join("utfq").join("config.toml");
+    global.is_file().then_some(global)
+}
+
+/// A config file with its profile and view applied.
+struct Config {
+    /// The settings, keyed like long options.
+    table: Table,
+    /// Paths to scan from the `root` key.
+    roots: Vec<PathBuf>,
+    /// The `[profile.NAME]` tables by name.
+    profiles: BTreeMap<String, Table>,
+    /// The `[view.NAME]` tables by name.
+    views: BTreeMap<String, Table>,
+}
+
+/// The config `text` read from `path`, with the `profile` and then the `view` on top.
+fn load(path: &Path, text: &str, profile: Option<&str>, view: Option<&str>) -> Result<Config> {
+    let mut table: Table = text
+        .parse()
+        .wrap_err_with(|| format!("failed to parse config {}", path.display()))?;
+    let profiles = take_sections(path, &mut table, "profile")?;
+    let views = take_sections(path, &mut table, "view")?;
+    if let Some(name) = profile {
+        table = with_section(table, find_section(path, &profiles, "profile", name)?);
+    }
+    if let Some(name) = view {
+        table = with_section(table, find_section(path, &views, "view", name)?);
+    }
+    let roots = take_roots(path, &mut table)?;
+    Ok(Config {
+        table,
+        roots,
+        profiles,
+        views,
+    })
+}
+
+/// Named tables of settings in the config file, like `[view.NAME]`.
+const SECTIONS: [&str; 2] = ["profile", "view"];
+
+/// Take the `[{kind}.NAME]` tables, or `[{kind}s.NAME]`, out of the config `table`.
+fn take_sections(path: &Path, table: &mut Table, kind: &str) -> Result<BTreeMap<String, Table>> {
+    let mut sections = BTreeMap::new();
+    for key in [kind.to_string(), format!("{kind}s")] {
+        let Some(value) = table.remove(&key) else {
+            continue;
+        };
+        let Value::Table(named) = value else {
+            bail!("{}: `{key}` should be a table of {kind}s", path.display());
+        };
+        for (name, section) in named {
+            let Value::Table(section) = section else {
+                bail!("{}: {kind} `{name}` should be a table", path.display());
+            };
+            // a section using another could refer back to itself
+            let nested = SECTIONS.iter().find(|&&other| {
+                section.contains_key(other) || section.contains_key(&format!("{other}s"))
+            });
+            if let Some(other) = nested {
+                bail!("{}: {kind} `{name}` cannot use a {other}", path.display());
+            }
+            sections.insert(name, section);
+        }
+    }
+    Ok(sections)
+}
+
+/// The section `name` of `sections`, or an error listing the ones there are.
+fn find_section<'a>(
+    path: &Path,
+    sections: &'a BTreeMap<String, Table>,
+    kind: &str,
+    name: &str,
+) -> Result<&'a Table> {
+    if let Some(section) = sections.get(name) {
+        return Ok(section);
+    }
+    let names: Vec<_> = sections.keys().map(|name| format!("`{name}`")).collect();
+    match names.is_empty() {
+        true => bail!(
+            "{}: unknown {kind} `{name}`, no {kind}s are defined",
+            path.display()
+        ),
+        false => bail!(
+            "{}: unknown {kind} `{name}`, expect one of {}",
+            path.display(),
+            names.join(", ")
+        ),
+    }
+}
+
+/// The config `table` with the keys of `section` on top, dropping those it conflicts with.
+fn with_section(mut table: Table, section: &Table) -> Table {
+    let id = |key: &str| key.replace('-', "_");
+    table.retain(|key, _| {
+        !section
+            .keys()
+            .any(|other| cli::conflicts(&id(key), &id(other)))
+    });
+    table.extend(section.clone());
+    table
+}
+
+/// Take the `root` key out of the config `table`, relative to the directory of `path`.
+fn take_roots(path: &Path, table: &mut Table) -> Result<Vec<PathBuf>> {
+    let values = match table.remove("root") {
+        None => return Ok(vec![]),
+        Some(Value::Array(values)) => values,
+        Some(value) => vec![value],
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    values
+        .iter()
+        .map(|value| match value.as_str() {
+            Some(root) => Ok(dir.join(root)),
+            None => bail!(
+                "{}: `root` should be a path or a list of paths",
+                path.display()
+            ),
+        })
+        .collect()
+}
+
+/// The options `view` stands for on the command line, like `--sort=date --done`.
+fn expansion(view: &Table) -> String {
+    let option = |long: &str, value: &Value| match value {
+        Value::String(s) => format!("--{long}={s}"),
+        value => format!("--{long}={value}"),
+    };
+    let mut options = vec![];
+    for (key, value) in view {
+        let long = key.replace('_', "-");
+        match value {
+            Value::Boolean(true) => options.push(format!("--{long}")),
+            Value::Boolean(false) => {}
+            Value::Array(values) => options.extend(values.iter().map(|v| option(&long, v))),
+            value => options.push(option(&long, value)),
+        }
+    }
+    options.join(" ")
+}
+
+/// `cmd` with the values of the config `table` read from `path` as defaults,
+/// leaving out the options already `given` and those conflicting with them.
+///
+/// Defaults are not checked for conflicts by clap, so two conflicting keys
+/// in the file are reported here.
+fn with_defaults(
+    mut cmd: clap::Command,
+    path: &Path,
+    table: &Table,
+    given: &ArgMatches,
+) -> Result<clap::Command> {
+    let on_command_line: Vec<_> = cmd
+        .get_arguments()
+        .map(|arg| arg.get_id().as_str())
+        .filter(|&id| given.value_source(id) == Some(ValueSource::CommandLine))
+        .map(str::to_string)
+        .collect();
+
+    let mut defaults: Vec<(&String, String, Vec<String>)> = vec![];
+    for (key, value) in table {
+        let (long, value) = match (key.as_str(), value) {
+            // `[aliases]` maps variants to names, one --alias each
+            ("aliases", Value::Table(aliases)) => {
+                let mut pairs = vec![];
+                for (variant, name) in aliases {
+                    let Some(name) = name.as_str() else {
+                        bail!("{}: alias `{variant}` should be a string", path.display());
+                    };
+                    pairs.push(Value::String(format!("{variant}={name}")));
+                }
+                ("alias".to_string(), &Value::Array(pairs))
+            }
+            _ => (key.replace('_', "-"), value),
+        };
+        let Some(arg) = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+        else {
+            bail!("{}: unknown key `{key}`", path.display());
+        };
+        let id = arg.get_id().to_string();
+        // the command line replaces rather than extends the file
+        if on_command_line.contains(&id) {
+            continue;
+        }
+        // the environment takes precedence over the file
+        let from_env = ENV_OPTIONS.iter().any(|&(option, var)| {
+            option == long && env::var_os(var).is_some_and(|v| !v.is_empty())
+        });
+        if from_env {
+            continue;
+        }
+        // and so does an option given on the command line it conflicts with
+        if on_command_line
+            .iter()
+            .any(|other| cli::conflicts(&id, other))
+        {
+            continue;
+        }
+        let values = match value {
+            Value::Boolean(true) => {
+                let values = match arg.get_action() {
+                    ArgAction::SetTrue => vec!["true".to_string()],
+                    // an option whose value may be left out, like --week
+                    _ => bare_values(&cmd, &long, &id),
+                };
+                if values.is_empty() {
+                    bail!("{}: `{key}` needs a value", path.display());
+                }
+                defaults.push((key, id, values));
+                continue;
+            }
+            // flags are off unless given
+            Value::Boolean(false) => continue,
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let mut parts = vec![];
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Integer(n) => n.to_string(),
+                _ => bail!(
+                    "{}: `{key}` should be a string, an integer or a boolean",
+                    path.display()
+                ),
+            };
+            match arg.get_value_delimiter() {
+                Some(delimiter) => parts.extend(value.split(delimiter).map(str::to_string)),
+                None => parts.push(value),
+            }
+        }
+        for part in &parts {
+            let parsed = cmd
+                .clone()
+                .try_get_matches_from(["utfq".to_string(), format!("--{long}={part}")]);
+            // alone the option may miss the ones it requires, only its value counts
+            if let Err(err) = parsed
+                && matches!(
+                    err.kind(),
+                    ErrorKind::InvalidValue | ErrorKind::ValueValidation
+                )
+            {
+                bail!(
+                    "{}: invalid `{key}`: {}",
+                    path.display(),
+                    clap_message(&err)
+                );
+            }
+        }
+        defaults.push((key, id, parts));
+    }
+
+    for (i, (key, id, _)) in defaults.iter().enumerate() {
+        let rest = &defaults[i + 1..];
+        if let Some((other, ..)) = rest.iter().find(|(_, other, _)| cli::conflicts(id, other)) {
+            bail!(
+                "{}: `{key}` and `{other}` cannot be used together",
+                path.display()
+            );
+        }
+    }
+    for (_, id, values) in defaults {
+        cmd = cmd.mut_arg(id, |arg| arg.default_values(values));
+    }
+    Ok(cmd)
+}
+
+/// The values `--{long}` gets when given without one, none if it needs one.
+fn bare_values(cmd: &clap::Command, long: &str, id: &str) -> Vec<String> {
+    let matches = cmd
+        .clone()
+        .try_get_matches_from(["utfq".to_string(), format!("--{long}")]);
+    matches
+        .ok()
+        .and_then(|matches| {
+            let values = matches.get_raw(id)?;
+            Some(values.map(|v| v.to_string_lossy().into_owned()).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// The message of a clap error without the `error:` prefix and the usage hints.
+fn clap_message(err: &clap::Error) -> String {
+    let rendered = err.to_string();
+    let lines: Vec<_> = rendered
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .map(str::trim)
+        .collect();
+    let message = lines.join(" ");
+    message
+        .strip_prefix("error: ")
+        .unwrap_or(&message)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Format, Sort};
+
+    /// The command line `args` parsed on top of the config `text`.
+    fn parse(text: &str, args: &[&str]) -> Result<Cli> {
+        let args: Vec<_> = std::iter::once("utfq")
+            .chain(args.iter().copied())
+            .collect();
+        let given = cli::command().try_get_matches_from(&args)?;
+        let cli = Cli::from_arg_matches(&given)?;
+        let path = Path::new("utfq.toml");
+        let config = load(path, text, cli.profile.as_deref(), cli.view_name())?;
+        let cmd = with_defaults(cli::command(), path, &config.table, &given)?;
+        let mut cli = Cli::from_arg_matches(&cmd.try_get_matches_from(&args)?)?;
+        cli.config_roots = config.roots;
+        Ok(cli.resolve()?)
+    }
+
+    const VIEWS: &str = "\
+sort = \"path\"
+format = \"csv\"
+
+[view.review]
+sort = \"date\"
+done = true
+count = true
+
+[view.today]
+sort = \"text\"
+";
+
+    #[test]
+    fn views_sit_below_flags() {
+        let cli = parse(VIEWS, &["@review", "--sort", "text"]).unwrap();
+        assert_eq!(cli.view.as_deref(), Some("review"));
+        assert_eq!(cli.sort, Sort::Text);
+        assert!(cli.done && cli.count);
+        // the view's --count replaces the file's format
+        assert_eq!(cli.format, None);
+
+        let cli = parse(VIEWS, &["--view", "today"]).unwrap();
+        assert_eq!(cli.sort, Sort::Text);
+        assert_eq!(cli.format, Some(Format::Csv));
+    }
+
+    #[test]
+    fn views_cannot_use_views() {
+        let text = format!("{VIEWS}\n[view.weekly]\nview = \"today\"\n");
+        let err = parse(&text, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "utfq.toml: view `weekly` cannot use a view"
+        );
+    }
+
+    #[test]
+    fn unknown_views_list_the_known_ones() {
+        let err = parse(VIEWS, &["@tday"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "utfq.toml: unknown view `tday`, expect one of `review`, `today`"
+        );
+    }
+
+    #[test]
+    fn view_expansion() {
+        let config = load(Path::new("utfq.toml"), VIEWS, None, None).unwrap();
+        assert_eq!(
+            expansion(&config.views["review"]),
+            "--count --done --sort=date"
+        );
+    }
+
+    #[test]
+    fn values_are_defaults() {
+        let cli = parse("format = \"json\"\nslow_files = true", &[]).unwrap();
+        assert_eq!(cli.format, Some(Format::Json));
+        assert_eq!(cli.slow_files, Some(10));
+
+        let cli = parse("sort = \"date\"", &["--sort", "text"]).unwrap();
+        assert_eq!(cli.sort, Sort::Text);
+    }
+
+    #[test]
+    fn aliases_table() {
+        let cli = parse("[aliases]\nbob = \"robert\"\nrob = \"robert\"", &[]).unwrap();
+        assert_eq!(cli.canonical_name("@Bob"), "robert");
+        assert_eq!(cli.canonical_name("rob"), "robert");
+        assert_eq!(cli.canonical_name("alice"), "alice");
+    }
+
+    #[test]
+    fn format_yields_to_count() {
+        let cli = parse("format = \"json\"", &["--count"]).unwrap();
+        assert!(cli.count);
+        assert_eq!(cli.format, None);
+    }
+
+    #[test]
+    fn format_yields_to_tui() {
+        let cli = parse("format = \"json\"", &["--tui"]).unwrap();
+        assert!(cli.tui);
+        assert_eq!(cli.format, None);
+    }
+
+    #[test]
+    fn sort_yields_to_overdue() {
+        let cli = parse("sort = \"text\"", &["--overdue"]).unwrap();
+        assert!(cli.overdue);
+        assert_eq!(cli.sort, Sort::Date);
+    }
+
+    #[test]
+    fn forecast_yields_to_all() {
+        let cli = parse("forecast = true", &["--all"]).unwrap();
+        assert!(cli.all);
+        assert!(!cli.forecast);
+    }
+
+    #[test]
+    fn conflicting_keys_are_rejected() {
+        let err = parse("count = true\nformat = \"json\"", &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "utfq.toml: `count` and `format` cannot be used together"
+        );
+    }
+
+    #[test]
+    fn invalid_values_name_the_key() {
+        let err = parse("sort = \"size\"", &[]).unwrap_err();
+        assert!(
+            err.to_string().starts_with("utfq.toml: invalid `sort`"),
+            "{err}"
+        );
+    }
+}
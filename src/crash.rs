@@ -0,0 +1,41 @@
+use std::{
+    cell::RefCell,
+    panic,
+    path::{Path, PathBuf},
+    process,
+};
+
+thread_local! {
+    static CURRENT_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Clears the current file when dropped.
+pub struct ProcessingGuard;
+
+impl Drop for ProcessingGuard {
+    fn drop(&mut self) {
+        CURRENT_FILE.with_borrow_mut(|file| *file = None);
+    }
+}
+
+/// Record `path` as the file being processed, until the guard is dropped.
+pub fn processing(path: &Path) -> ProcessingGuard {
+    CURRENT_FILE.with_borrow_mut(|file| *file = Some(path.to_path_buf()));
+    ProcessingGuard
+}
+
+/// Wrap the installed panic hook to also report the file being processed.
+pub fn install_hook() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        hook(info);
+        eprintln!("utfq {} crashed", env!("CARGO_PKG_VERSION"));
+        if let Some(path) = CURRENT_FILE.with_borrow(|file| file.clone()) {
+            eprintln!("while processing: {}", path.display());
+            eprintln!("please file an issue, attaching this file if possible");
+        } else {
+            eprintln!("please file an issue");
+        }
+        process::exit(101);
+    }));
+}
@@ -0,0 +1,146 @@
+//! Date arithmetic shared by every feature, so the edge cases live in one place.
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate, Weekday};
+
+/// Today in the local timezone.
+///
+/// Only the local calendar date is taken, all further arithmetic is on
+/// naive dates, so DST transitions never change which day it is.
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// `date` moved by `days`, which may be negative, `None` when out of range.
+pub fn add_days(date: NaiveDate, days: i64) -> Option<NaiveDate> {
+    let magnitude = Days::new(days.unsigned_abs());
+    match days < 0 {
+        true => date.checked_sub_days(magnitude),
+        false => date.checked_add_days(magnitude),
+    }
+}
+
+/// `date` moved by `months`, which may be negative, `None` when out of range.
+///
+/// Days past the end of the target month are clamped to its last day, so
+/// Jan 31 plus one month is Feb 28, or Feb 29 in a leap year.
+pub fn add_months_clamped(date: NaiveDate, months: i32) -> Option<NaiveDate> {
+    let magnitude = Months::new(months.unsigned_abs());
+    match months < 0 {
+        true => date.checked_sub_months(magnitude),
+        false => date.checked_add_months(magnitude),
+    }
+}
+
+/// The span from `start` to `end` moved by `days`, keeping its length.
+pub fn shift_span(start: NaiveDate, end: NaiveDate, days: i64) -> Option<(NaiveDate, NaiveDate)> {
+    Some((add_days(start, days)?, add_days(end, days)?))
+}
+
+/// The day of the week `date` falls on.
+pub fn weekday_of(date: NaiveDate) -> Weekday {
+    date.weekday()
+}
+
+/// Days from `from` to `to`, negative when `to` is earlier.
+pub fn days_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    (to - from).num_days()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn months_clamp_to_the_month_end() {
+        assert_eq!(
+            add_months_clamped(date("2025-01-31"), 1),
+            Some(date("2025-02-28"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2025-03-31"), 1),
+            Some(date("2025-04-30"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2025-03-31"), -1),
+            Some(date("2025-02-28"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2025-05-31"), -1),
+            Some(date("2025-04-30"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2025-01-15"), 0),
+            Some(date("2025-01-15"))
+        );
+    }
+
+    #[test]
+    fn leap_years() {
+        assert_eq!(
+            add_months_clamped(date("2024-01-31"), 1),
+            Some(date("2024-02-29"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2024-02-29"), 12),
+            Some(date("2025-02-28"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2024-02-29"), 48),
+            Some(date("2028-02-29"))
+        );
+        // centuries are leap years only when divisible by 400
+        assert_eq!(
+            add_months_clamped(date("2100-01-31"), 1),
+            Some(date("2100-02-28"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2000-01-31"), 1),
+            Some(date("2000-02-29"))
+        );
+        assert_eq!(add_days(date("2024-02-28"), 1), Some(date("2024-02-29")));
+        assert_eq!(add_days(date("2025-02-28"), 1), Some(date("2025-03-01")));
+        assert_eq!(days_between(date("2024-02-01"), date("2024-03-01")), 29);
+        assert_eq!(days_between(date("2025-02-01"), date("2025-03-01")), 28);
+    }
+
+    #[test]
+    fn year_boundaries() {
+        assert_eq!(add_days(date("2024-12-31"), 1), Some(date("2025-01-01")));
+        assert_eq!(add_days(date("2025-01-01"), -1), Some(date("2024-12-31")));
+        assert_eq!(
+            add_months_clamped(date("2024-12-15"), 1),
+            Some(date("2025-01-15"))
+        );
+        assert_eq!(
+            add_months_clamped(date("2025-01-31"), -2),
+            Some(date("2024-11-30"))
+        );
+        assert_eq!(days_between(date("2024-12-31"), date("2025-01-01")), 1);
+        assert_eq!(days_between(date("2025-01-01"), date("2024-01-01")), -366);
+        assert_eq!(
+            shift_span(date("2024-12-30"), date("2025-01-05"), 7),
+            Some((date("2025-01-06"), date("2025-01-12")))
+        );
+    }
+
+    #[test]
+    fn out_of_range_is_none() {
+        assert_eq!(add_days(NaiveDate::MAX, 1), None);
+        assert_eq!(add_days(NaiveDate::MIN, -1), None);
+        assert_eq!(add_days(NaiveDate::MAX, i64::MIN), None);
+        assert_eq!(add_months_clamped(NaiveDate::MAX, 1), None);
+        assert_eq!(add_months_clamped(NaiveDate::MIN, i32::MIN), None);
+        assert_eq!(shift_span(NaiveDate::MIN, NaiveDate::MAX, 1), None);
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(weekday_of(date("2025-03-10")), Weekday::Mon);
+        assert_eq!(weekday_of(date("2024-02-29")), Weekday::Thu);
+        assert_eq!(weekday_of(date("2000-01-01")), Weekday::Sat);
+    }
+}
@@ -0,0 +1,41 @@
+//! The log on stderr, held back while the TUI owns the terminal.
+
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+};
+
+/// Log output written since [`hold`], `None` when it goes straight to stderr.
+static HELD: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Writer of the log, to stderr unless held.
+pub struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match HELD.lock().unwrap().as_mut() {
+            Some(held) => {
+                held.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Buffer the log instead of drawing it over the TUI.
+pub fn hold() {
+    *HELD.lock().unwrap() = Some(vec![]);
+}
+
+/// Write out the log held since [`hold`], and stop holding it.
+pub fn release() {
+    if let Some(held) = HELD.lock().unwrap().take() {
+        // nowhere left to report a closed stderr
+        let _ = io::stderr().write_all(&held);
+    }
+}
@@ -1,19 +1,113 @@
-use clap::Parser;
+use std::{io::Write, process::ExitCode};
+
 use color_eyre::eyre;
+use tracing_subscriber::EnvFilter;
 
 use crate::{app::App, cli::Cli};
 
 mod app;
+mod calendar;
 mod cli;
+mod clipboard;
+mod config;
+mod crash;
+mod datemath;
+mod logging;
+mod output;
 mod parse;
+mod stats;
 mod walk;
+mod watch;
+
+/// Exit code for failures, so scripts can tell them from --fail-if-* hits.
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let cli = match config::parse_cli() {
+        Ok(cli) => cli,
+        Err(err) => return report(err),
+    };
+    init_logging(&cli);
+    // options that print something and exit
+    let listing = match () {
+        _ if cli.build_info => vec![cli::build_info()],
+        _ if cli.views => (cli.view_options.iter())
+            .map(|(name, options)| format!("{name}: {options}"))
+            .collect(),
+        _ if cli.profiles => (cli.profile_roots.iter())
+            .map(|(name, roots)| {
+                let roots: Vec<_> = roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect();
+                format!("{name}: {}", roots.join(", "))
+            })
+            .collect(),
+        _ => return run(cli).unwrap_or_else(report),
+    };
+    match output::to_stdout(|w| listing.iter().try_for_each(|line| writeln!(w, "{line}"))) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => report(err.into()),
+    }
+}
+
+fn report(err: eyre::Report) -> ExitCode {
+    eprintln!("Error: {err:?}");
+    ExitCode::from(EXIT_ERROR)
+}
+
+/// Log to stderr at the level from -v/-q, `RUST_LOG` takes precedence.
+///
+/// The TUI holds the log back until the terminal is restored.
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("utfq={level}")));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| logging::Stderr)
+        .without_time()
+        .init();
+}
 
-fn main() -> eyre::Result<()> {
-    let cli = Cli::parse();
+fn run(cli: Cli) -> eyre::Result<ExitCode> {
     color_eyre::install()?;
+    crash::install_hook();
 
-    let mut app = App::new(cli);
-    ratatui::run(|terminal| app.run(terminal))?;
+    let (count, stats, format) = (cli.count, cli.stats, cli.format);
+    let (calendar, week, template) = (cli.calendar, cli.week, cli.template.is_some());
+    let review_early = cli.review_early;
+    let mut app = App::new(cli)?;
+    match format {
+        _ if count => app.print_count()?,
+        _ if stats => app.print_stats(format)?,
+        _ if calendar.is_some() => app.print_calendar(calendar.flatten())?,
+        _ if week.is_some() => app.print_week(week.unwrap_or_default())?,
+        _ if template => app.print_template()?,
+        None => {
+            logging::hold();
+            let result = ratatui::run(|terminal| app.run(terminal));
+            logging::release();
+            result?
+        }
+        Some(format) => app.print(format)?,
+    }
+    app.print_slow_files();
+    // after the output, so the TUI and pipes are left alone
+    match review_early {
+        true => app.review_early()?,
+        false => app.report_findings(),
+    }
 
-    Ok(())
+    match (app.had_errors(), app.failed()) {
+        (true, _) => Ok(ExitCode::from(EXIT_ERROR)),
+        (false, true) => Ok(ExitCode::FAILURE),
+        (false, false) => Ok(ExitCode::SUCCESS),
+    }
 }
@@ -0,0 +1,389 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, BufWriter, StderrLock, StdoutLock, Write},
+    ops::RangeInclusive,
+    path::Path,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{datemath, parse::Item};
+
+/// Key of the items read from stdin with `-`.
+pub const STDIN_PATH: &str = "<stdin>";
+/// Key of the items read from the clipboard with --clipboard.
+pub const CLIPBOARD_PATH: &str = "<clipboard>";
+
+/// Whether `path` keys items read from stdin or the clipboard rather than a file.
+pub fn is_pasted(path: &Path) -> bool {
+    path == Path::new(STDIN_PATH) || path == Path::new(CLIPBOARD_PATH)
+}
+
+/// Run `f` on a buffered stdout, treating a closed pipe as a clean finish.
+pub fn to_stdout(
+    f: impl FnOnce(&mut BufWriter<StdoutLock<'static>>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(io::stdout().lock());
+    match f(&mut w).and_then(|()| w.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Run `f` on stderr, for reports that must stay out of the output.
+///
+/// A closed stderr is ignored, there is nowhere left to report it.
+pub fn to_stderr(f: impl FnOnce(&mut StderrLock<'static>) -> io::Result<()>) {
+    let _ = f(&mut io::stderr().lock());
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    path: Cow<'a, str>,
+    #[serde(flatten)]
+    item: &'a Item,
+    overdue_bucket: String,
+    file_mtime: Option<DateTime<Utc>>,
+}
+
+/// Rank of the bucket of `overdue_days` among those bounded by `bounds`,
+/// 0 when not overdue and higher the longer it is.
+pub fn overdue_rank(overdue_days: Option<i64>, bounds: &[u64]) -> usize {
+    let Some(days) = overdue_days else {
+        return 0;
+    };
+    let below = bounds.iter().position(|&bound| days < bound as i64);
+    below.unwrap_or(bounds.len()) + 1
+}
+
+/// Name of the bucket of rank `rank`, like `on-time`, `<1w` or `older`.
+pub fn bucket_label(rank: usize, bounds: &[u64]) -> String {
+    match rank.checked_sub(1).map(|i| bounds.get(i)) {
+        None => "on-time".to_string(),
+        Some(Some(&bound)) => format!("<{}", days_label(bound)),
+        Some(None) => "older".to_string(),
+    }
+}
+
+/// A number of days in the largest whole unit, like `3d`, `1w` or `3m`.
+fn days_label(days: u64) -> String {
+    match days {
+        _ if days.is_multiple_of(30) => format!("{}m", days / 30),
+        _ if days.is_multiple_of(7) => format!("{}w", days / 7),
+        _ => format!("{days}d"),
+    }
+}
+
+/// Classify how long an item has been overdue, coarser as it gets older.
+pub fn overdue_bucket(overdue_days: Option<i64>, bounds: &[u64]) -> String {
+    bucket_label(overdue_rank(overdue_days, bounds), bounds)
+}
+
+/// Write the items as a JSON array, overdue as seen from `date`.
+pub fn write_json(
+    w: &mut impl Write,
+    entries: &[(&Path, &Item)],
+    date: NaiveDate,
+    buckets: &[u64],
+    modified: &HashMap<&Path, Option<SystemTime>>,
+) -> io::Result<()> {
+    let items: Vec<_> = entries
+        .iter()
+        .map(|(path, item)| JsonItem {
+            path: path.to_string_lossy(),
+            item,
+            overdue_bucket: overdue_bucket(item.overdue_days(date), buckets),
+            file_mtime: modified.get(*path).copied().flatten().map(DateTime::from),
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *w, &items)?;
+    writeln!(w)
+}
+
+/// Write the items as CSV rows, optionally preceded by a header row.
+pub fn write_csv(w: &mut impl Write, entries: &[(&Path, &Item)], header: bool) -> io::Result<()> {
+    if header {
+        writeln!(w, "file,line,done,date,text")?;
+    }
+    for (path, item) in entries {
+        let date = item.date.map(|d| d.to_string()).unwrap_or_default();
+        writeln!(
+            w,
+            "{},{},{},{date},{}",
+            escape_csv(&path.to_string_lossy()),
+            item.line,
+            item.done,
+            escape_csv(&item.text)
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote a field if it contains a separator, quote or line break.
+fn escape_csv(field: &str) -> Cow<'_, str> {
+    match field.contains([',', '"', '\r', '\n']) {
+        true => Cow::Owned(format!("\"{}\"", field.replace('"', "\"\""))),
+        false => Cow::Borrowed(field),
+    }
+}
+
+/// Placeholders understood by --template.
+pub const TEMPLATE_FIELDS: [&str; 10] = [
+    "file",
+    "line",
+    "text",
+    "summary",
+    "due",
+    "checked",
+    "rel_due",
+    "tags",
+    "context",
+    "overdue_bucket",
+];
+
+/// Names of the `{name}` placeholders in a template, in order.
+pub fn template_fields(template: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        fields.push(&rest[start + 1..start + end]);
+        rest = &rest[start + end + 1..];
+    }
+    fields
+}
+
+/// Write each item as `template` with its placeholders filled, missing values empty.
+///
+/// The overdue bucket is as seen from `date`.
+pub fn write_template(
+    w: &mut impl Write,
+    entries: &[(&Path, &Item)],
+    template: &str,
+    date: NaiveDate,
+    buckets: &[u64],
+) -> io::Result<()> {
+    let today = datemath::today();
+    for (path, item) in entries {
+        let value = |field: &str| match field {
+            "file" => path.to_string_lossy().into_owned(),
+            "line" => item.line.to_string(),
+            "text" => item.text.clone(),
+            "summary" => item.summary(),
+            "due" => item.date.map(|d| d.to_string()).unwrap_or_default(),
+            "checked" => match item.done {
+                true => "x".to_string(),
+                false => " ".to_string(),
+            },
+            "rel_due" => item
+                .date
+                .map(|d| relative_due(d, item.done, today))
+                .unwrap_or_default(),
+            "tags" => item.tags.join(","),
+            "context" => item.context.join(" > "),
+            "overdue_bucket" => overdue_bucket(item.overdue_days(date), buckets),
+            _ => String::new(),
+        };
+
+        let mut line = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            line.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                rest = &rest[start..];
+                break;
+            };
+            line.push_str(&value(&rest[start + 1..start + end]));
+            rest = &rest[start + end + 1..];
+        }
+        line.push_str(rest);
+        writeln!(w, "{line}")?;
+    }
+    Ok(())
+}
+
+/// A date relative to `today`, like `due in 3d` or `overdue 2d`.
+pub fn relative_due(date: NaiveDate, done: bool, today: NaiveDate) -> String {
+    let days = datemath::days_between(today, date);
+    match days {
+        0 => "due today".to_string(),
+        1.. => format!("due in {days}d"),
+        _ if done => format!("was due {}d ago", -days),
+        _ => format!("overdue {}d", -days),
+    }
+}
+
+/// Write the items as todo.txt lines, with the file stem as project.
+///
+/// Items from stdin or the clipboard have no file, so no project either.
+pub fn write_todotxt(w: &mut impl Write, entries: &[(&Path, &Item)]) -> io::Result<()> {
+    for (path, item) in entries {
+        if item.done {
+            write!(w, "x ")?;
+        }
+        write!(w, "{}", item.summary())?;
+        if let Some(date) = item.date {
+            write!(w, " due:{date}")?;
+        }
+        if let Some(stem) = path.file_stem().filter(|_| !is_pasted(path)) {
+            let stem = stem.to_string_lossy();
+            let project: Vec<_> = stem.split_whitespace().collect();
+            write!(w, " +{}", project.join("-"))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write the items as an iCalendar document with one VTODO each.
+pub fn write_ics(w: &mut impl Write, entries: &[(&Path, &Item)]) -> io::Result<()> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+
+    write_ics_line(w, "BEGIN:VCALENDAR")?;
+    write_ics_line(w, "VERSION:2.0")?;
+    write_ics_line(w, "PRODID:-//utfq//utfq//EN")?;
+    for (path, item) in entries {
+        // stable across runs as long as the file and the line stay the same
+        let hash = Sha256::digest(format!("{}\0{}", path.to_string_lossy(), item.text));
+        let uid: String = hash[..16].iter().map(|b| format!("{b:02x}")).collect();
+
+        write_ics_line(w, "BEGIN:VTODO")?;
+        write_ics_line(w, &format!("UID:{uid}@utfq"))?;
+        write_ics_line(w, &format!("DTSTAMP:{stamp}"))?;
+        write_ics_line(w, &format!("SUMMARY:{}", escape_ics(&item.summary())))?;
+        if let Some(date) = item.date {
+            write_ics_line(w, &format!("DUE;VALUE=DATE:{}", date.format("%Y%m%d")))?;
+        }
+        let status = match item.done {
+            true => "COMPLETED",
+            false => "NEEDS-ACTION",
+        };
+        write_ics_line(w, &format!("STATUS:{status}"))?;
+        write_ics_line(w, "END:VTODO")?;
+    }
+    write_ics_line(w, "END:VCALENDAR")
+}
+
+/// Write one `YYYY-MM-DD.ics` per day and an `index.ics` with everything into `dir`.
+///
+/// Day files from earlier runs whose day is in `queried` but has no items
+/// anymore are removed, other files in `dir` are never touched.
+pub fn write_ics_days(
+    dir: &Path,
+    entries: &[(&Path, &Item)],
+    queried: Option<RangeInclusive<NaiveDate>>,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut days: BTreeMap<NaiveDate, Vec<(&Path, &Item)>> = BTreeMap::new();
+    for &(path, item) in entries {
+        if let Some(date) = item.date {
+            days.entry(date).or_default().push((path, item));
+        }
+    }
+    for (date, entries) in &days {
+        write_atomic(&dir.join(format!("{date}.ics")), |w| write_ics(w, entries))?;
+    }
+    write_atomic(&dir.join("index.ics"), |w| write_ics(w, entries))?;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(stem) = name.to_str().and_then(|n| n.strip_suffix(".ics")) else {
+            continue;
+        };
+        if let Ok(date) = NaiveDate::parse_from_str(stem, "%Y-%m-%d")
+            && date.to_string() == stem
+            && queried.as_ref().is_some_and(|days| days.contains(&date))
+            && !days.contains_key(&date)
+        {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write to a temporary file next to `path`, then rename it over `path`.
+pub fn write_atomic(
+    path: &Path,
+    f: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    f(&mut w)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Escape a TEXT value.
+fn escape_ics(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write a content line, folded at 75 octets.
+fn write_ics_line(w: &mut impl Write, line: &str) -> io::Result<()> {
+    let mut rest = line;
+    // continuation lines start with a space, which counts toward the limit
+    let mut limit = 75;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(w, "{}\r\n ", &rest[..end])?;
+        rest = &rest[end..];
+        limit = 74;
+    }
+    write!(w, "{rest}\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overdue_bucket_boundaries() {
+        let cases = [
+            (None, "on-time"),
+            (Some(1), "<3d"),
+            (Some(2), "<3d"),
+            (Some(3), "<1w"),
+            (Some(6), "<1w"),
+            (Some(7), "<1m"),
+            (Some(29), "<1m"),
+            (Some(30), "<3m"),
+            (Some(89), "<3m"),
+            (Some(90), "older"),
+            (Some(i64::MAX), "older"),
+        ];
+        for (days, bucket) in cases {
+            assert_eq!(overdue_bucket(days, &[3, 7, 30, 90]), bucket, "{days:?}");
+        }
+
+        let bounds = [1, 14, 60];
+        assert_eq!(overdue_bucket(Some(1), &bounds), "<2w");
+        assert_eq!(overdue_bucket(Some(59), &bounds), "<2m");
+        assert_eq!(overdue_bucket(Some(60), &bounds), "older");
+        assert_eq!(overdue_bucket(Some(5), &[]), "older");
+    }
+}
@@ -1,38 +1,525 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, ParseError};
+use serde::Serialize;
+use tracing::debug;
 
-use crate::cli::Cli;
+use crate::{
+    cli::Cli,
+    datemath::{self, add_months_clamped, days_between},
+};
+
+/// How far into a file to look for the whole-file ignore pragma.
+const PRAGMA_SCAN_BYTES: usize = 1024;
+const PRAGMA_IGNORE: &str = "<!-- utfq: ignore -->";
+const PRAGMA_IGNORE_ABOVE: &str = "<!-- utfq: ignore-above -->";
+const PRAGMA_IGNORE_BELOW: &str = "<!-- utfq: ignore-below -->";
+/// A value-less marker, tracked but intentionally unscheduled.
+const SOMEDAY_MARKER: &str = "<agmd:>";
+
+/// An agmd item found in a markdown file.
+#[derive(Serialize, Debug, Clone)]
+pub struct Item {
+    /// Whether the task is checked.
+    pub done: bool,
+    /// The trimmed source line.
+    pub text: String,
+    /// Date of the first dated marker on the line.
+    pub date: Option<NaiveDate>,
+    /// Where the date comes from when not from a marker, like `comment`.
+    pub date_source: Option<&'static str>,
+    /// Whether the line has markers but none of them holds a date.
+    pub malformed: bool,
+    /// Why the first marker of a malformed line holds no date.
+    pub error: Option<String>,
+    /// `#tag` tags in the text, without the `#`.
+    pub tags: Vec<String>,
+    /// `@name` mentions in the text, without the `@`.
+    pub assignees: Vec<String>,
+    /// 1-based line number in the source file.
+    pub line: usize,
+    /// Texts of the headings the item is under, outermost first.
+    pub context: Vec<String>,
+}
+
+impl Item {
+    fn from_line(line: &str, number: usize, context: Vec<String>) -> Self {
+        let values: Vec<_> = agmd_values(line).filter(|v| !v.is_empty()).collect();
+        let date = values
+            .iter()
+            .find_map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok());
+        // a marker missing its `>` yields no value at all
+        let unclosed = line.split("<agmd:").skip(1).any(|rest| !rest.contains('>'));
+        let error = match values.first() {
+            _ if date.is_some() => None,
+            Some(v) => NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                .err()
+                .map(|err| marker_error(v, err)),
+            None if unclosed => Some("unclosed marker, expect <agmd:YYYY-MM-DD>".to_string()),
+            None => None,
+        };
+        Self {
+            done: line.contains(" [x]"),
+            text: line.trim().to_string(),
+            date,
+            date_source: None,
+            malformed: date.is_none() && (!values.is_empty() || unclosed),
+            error,
+            tags: tags(line).into_iter().map(String::from).collect(),
+            assignees: mentions(line).into_iter().map(String::from).collect(),
+            line: number,
+            context,
+        }
+    }
+
+    /// Days past the item date, if it is open and overdue on `today`.
+    pub fn overdue_days(&self, today: NaiveDate) -> Option<i64> {
+        let days = days_between(self.date?, today);
+        (!self.done && days > 0).then_some(days)
+    }
+
+    /// Whether the item is checked but dated more than `days` after `today`.
+    pub fn done_early(&self, today: NaiveDate, days: i64) -> bool {
+        self.done
+            && self
+                .date
+                .is_some_and(|date| days_between(today, date) > days)
+    }
+
+    /// The task text without list marker, checkbox and agmd markers.
+    pub fn summary(&self) -> String {
+        let mut text = self.text.as_str();
+        if let Some(rest) = ["- ", "* ", "+ "].iter().find_map(|m| text.strip_prefix(m)) {
+            text = rest;
+        }
+        if let Some(rest) = ["[ ] ", "[x] ", "[X] "]
+            .iter()
+            .find_map(|m| text.strip_prefix(m))
+        {
+            text = rest;
+        }
+
+        let mut summary = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("<agmd:") {
+            summary.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('>') else {
+                rest = &rest[start..];
+                break;
+            };
+            rest = &rest[start + end + 1..];
+        }
+        summary.push_str(rest);
+        summary.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// A dated line --pedantic warns about, whatever date is viewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Finding {
+    /// 1-based line number in the source file.
+    pub line: usize,
+    /// Date of the first dated marker on the line.
+    pub date: NaiveDate,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// Checked, but dated more than --done-early-days after the viewed date.
+    DoneEarly,
+    /// Dated more than --date-horizon-years after today.
+    FarFuture,
+    /// Dated more than --date-horizon-years before today.
+    FarPast,
+}
 
 /// 解析文件，同时生成链接。
-pub fn parse_file(cli: &Cli, date: NaiveDate, text: &str) -> Vec<(bool, String)> {
+///
+/// With --pedantic, lines worth a warning are added to `findings`.
+pub fn parse_file(
+    cli: &Cli,
+    date: NaiveDate,
+    text: &str,
+    findings: &mut Vec<Finding>,
+) -> Vec<Item> {
     let mut items = vec![];
-    let agmd_str = format!("<agmd:{}>", date);
+    let date_str = date.to_string();
 
-    if !text.contains("<agmd:") {
+    if (!text.contains("<agmd:") && !cli.html_comment_dates) || has_ignore_pragma(text) {
         return items;
     }
 
-    for line in text.lines() {
+    // lines on or outside the region markers are skipped
+    let lines: Vec<_> = text.lines().collect();
+    let first = lines
+        .iter()
+        .rposition(|l| l.contains(PRAGMA_IGNORE_ABOVE))
+        .map_or(0, |i| i + 1);
+    let last = lines
+        .iter()
+        .position(|l| l.contains(PRAGMA_IGNORE_BELOW))
+        .unwrap_or(lines.len());
+
+    // character and length of the fence of the code block we are in, if any
+    let mut fence: Option<(char, usize)> = None;
+    // text of the current heading of each level, `#` first
+    let mut headings: [Option<String>; 6] = Default::default();
+    // dates outside are probably typos, like 2205 for 2025
+    let horizon_months = i32::try_from(u64::from(cli.date_horizon_years) * 12).unwrap_or(i32::MAX);
+    let today = datemath::today();
+    let earliest = add_months_clamped(today, -horizon_months).unwrap_or(NaiveDate::MIN);
+    let latest = add_months_clamped(today, horizon_months).unwrap_or(NaiveDate::MAX);
+
+    let region = lines.get(first..last).unwrap_or_default();
+    for (i, &line) in region.iter().enumerate() {
+        if let Some(open) = fence {
+            if closes_fence(line, open) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(open) = opening_fence(line) {
+            fence = Some(open);
+            continue;
+        }
         if line.starts_with("<!--") {
             continue;
         }
-        let mut matched = false;
-        match cli.all {
-            true => {
-                if line.contains("<agmd:") {
-                    matched = true;
-                }
+        // a heading line may hold markers too, under its parent headings only
+        let current = heading(line);
+        if let Some((level, _)) = current {
+            headings[level - 1..].fill(None);
+        }
+        // dates from a comment right below a task without annotation
+        if cli.html_comment_dates && !cli.only_someday && is_task(line) && !line.contains("<agmd:")
+        {
+            let comment_date = region.get(i + 1).and_then(|next| comment_date(next));
+            if let Some(comment_date) = comment_date
+                && (cli.all || cli.overdue || comment_date == date)
+            {
+                items.push(Item {
+                    done: line.contains(" [x]"),
+                    text: line.trim().to_string(),
+                    date: Some(comment_date),
+                    date_source: Some("comment"),
+                    malformed: false,
+                    error: None,
+                    tags: tags(line).into_iter().map(String::from).collect(),
+                    assignees: mentions(line).into_iter().map(String::from).collect(),
+                    line: first + i + 1,
+                    context: headings.iter().flatten().cloned().collect(),
+                });
             }
-            false => {
-                if line.contains(&agmd_str) {
-                    matched = true;
-                }
+            continue;
+        }
+        if cli.pedantic && line.contains("<agmd:") {
+            let item = Item::from_line(line, first + i + 1, vec![]);
+            if let Some(due) = item.date
+                && item.done_early(date, cli.done_early_days)
+            {
+                findings.push(Finding {
+                    line: item.line,
+                    date: due,
+                    kind: FindingKind::DoneEarly,
+                });
+            }
+            // every marker, not only the first dated one
+            let dates =
+                agmd_values(line).filter_map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok());
+            for marked in dates {
+                let kind = match marked {
+                    _ if marked > latest => FindingKind::FarFuture,
+                    _ if marked < earliest => FindingKind::FarPast,
+                    _ => continue,
+                };
+                findings.push(Finding {
+                    line: item.line,
+                    date: marked,
+                    kind,
+                });
             }
         }
+        let someday = line.contains(SOMEDAY_MARKER);
+        let matched = match (cli.only_someday, cli.all) {
+            _ if cli.malformed || cli.overdue => line.contains("<agmd:") && !someday,
+            (true, _) => someday,
+            (false, true) => line.contains("<agmd:") && (cli.someday || !someday),
+            (false, false) => agmd_values(line).any(|v| v == date_str) || (cli.someday && someday),
+        };
         if matched {
-            let done = line.contains(" [x]");
-            items.push((done, line.trim().to_string()));
+            let context = headings.iter().flatten().cloned().collect();
+            items.push(Item::from_line(line, first + i + 1, context));
+        }
+        if let Some((level, text)) = current {
+            headings[level - 1] = Some(text.to_string());
         }
     }
 
+    if cli.malformed {
+        retain_logged(&mut items, "--malformed", |item| item.malformed);
+    }
+
+    // only the date counts, whatever day is being viewed
+    if cli.overdue {
+        let today = datemath::today();
+        retain_logged(&mut items, "--overdue", |item| {
+            item.overdue_days(today).is_some()
+        });
+    }
+
+    if let Some(min) = cli.min_overdue {
+        let today = datemath::today();
+        retain_logged(&mut items, "--min-overdue", |item| {
+            item.overdue_days(today).is_some_and(|days| days >= min)
+        });
+    }
+
+    if !cli.tag.is_empty() {
+        let has_tag = |item: &Item, wanted: &String| {
+            let wanted = wanted.trim_start_matches('#');
+            item.tags.iter().any(|t| t.eq_ignore_ascii_case(wanted))
+        };
+        retain_logged(&mut items, "--tag", |item| match cli.any_tag {
+            true => cli.tag.iter().any(|wanted| has_tag(item, wanted)),
+            false => cli.tag.iter().all(|wanted| has_tag(item, wanted)),
+        });
+    }
+
+    if !cli.alias.is_empty() {
+        for item in &mut items {
+            let mut names: Vec<String> = vec![];
+            for mention in &item.assignees {
+                let name = cli.canonical_name(mention);
+                if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                    names.push(name.to_string());
+                }
+            }
+            item.assignees = names;
+        }
+    }
+
+    if !cli.assignee.is_empty() {
+        retain_logged(&mut items, "--assignee", |item| {
+            item.assignees.iter().any(|mention| {
+                cli.assignee
+                    .iter()
+                    .any(|a| cli.canonical_name(a).eq_ignore_ascii_case(mention))
+            })
+        });
+    }
+
     items
 }
+
+/// `text` with the checkbox on 1-based `line` cleared, `None` if it holds no checked box.
+pub fn uncheck_line(text: &str, line: usize) -> Option<String> {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum();
+    let rest = &text[start..];
+    let end = rest.find('\n').unwrap_or(rest.len());
+    let checkbox = start + rest[..end].find(" [x]")?;
+    let mut text = text.to_string();
+    text.replace_range(checkbox + 2..checkbox + 3, " ");
+    Some(text)
+}
+
+/// Keep the items matching `keep`, logging how many `filter` dropped.
+pub fn retain_logged(items: &mut Vec<Item>, filter: &str, keep: impl FnMut(&Item) -> bool) {
+    let before = items.len();
+    items.retain(keep);
+    let dropped = before - items.len();
+    if dropped > 0 {
+        debug!("{filter} filtered out {dropped} items");
+    }
+}
+
+/// Whether the head of the file carries `<!-- utfq: ignore -->`.
+pub fn has_ignore_pragma(text: &str) -> bool {
+    let head = &text.as_bytes()[..text.len().min(PRAGMA_SCAN_BYTES)];
+    head.windows(PRAGMA_IGNORE.len())
+        .any(|w| w == PRAGMA_IGNORE.as_bytes())
+}
+
+/// Values of the `<agmd:VALUE>` markers in a line.
+///
+/// WYSIWYG editors sometimes wrap the value in quotes or brackets, one
+/// matching layer of those is stripped.
+fn agmd_values(line: &str) -> impl Iterator<Item = &str> {
+    line.split("<agmd:").skip(1).filter_map(|rest| {
+        let value = rest.split_once('>')?.0;
+        let unwrapped = [('"', '"'), ('\'', '\''), ('[', ']')]
+            .into_iter()
+            .find_map(|(open, close)| value.strip_prefix(open)?.strip_suffix(close));
+        Some(unwrapped.unwrap_or(value))
+    })
+}
+
+/// Why a marker value is not a `YYYY-MM-DD` date.
+fn marker_error(value: &str, err: ParseError) -> String {
+    match value.split_once(['=', ':']) {
+        Some((key, _)) => format!("`{key}` key, markers hold a bare date like <agmd:2025-12-01>"),
+        None => format!("invalid date \"{value}\": {err}, expect YYYY-MM-DD"),
+    }
+}
+
+/// `@name` mentions in a line, ignoring code spans and email addresses.
+fn mentions(line: &str) -> Vec<&str> {
+    sigil_words(line, '@', &['_', '.', '-'])
+}
+
+/// `#tag` tags in a line, ignoring code spans and headings.
+fn tags(line: &str) -> Vec<&str> {
+    sigil_words(line, '#', &['_', '-', '/'])
+}
+
+/// Words following `sigil` outside code spans, made of alphanumerics and `extra`.
+///
+/// The sigil only counts at the start of a word, so `a@b.c` is not a mention.
+fn sigil_words<'a>(line: &'a str, sigil: char, extra: &[char]) -> Vec<&'a str> {
+    let is_word_char = |c: char| c.is_alphanumeric() || extra.contains(&c);
+    let mut words = vec![];
+    let mut in_code = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if c == sigil && !in_code && !is_word_char(prev) && prev != sigil {
+            let rest = &line[i + c.len_utf8()..];
+            let end = rest.find(|c: char| !is_word_char(c)).unwrap_or(rest.len());
+            let word = rest[..end].trim_end_matches(extra);
+            if !word.is_empty() {
+                words.push(word);
+            }
+        }
+        prev = c;
+    }
+    words
+}
+
+/// Character and length of the code fence opened by `line`, if it opens one.
+fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start();
+    let c = trimmed.chars().next().filter(|c| ['`', '~'].contains(c))?;
+    let len = trimmed.chars().take_while(|&x| x == c).count();
+    // the info string of a backtick fence cannot hold backticks
+    (len >= 3 && !(c == '`' && trimmed[len..].contains('`'))).then_some((c, len))
+}
+
+/// Whether `line` closes the code block opened by `open`: the same character,
+/// at least as many times, and nothing after it.
+fn closes_fence(line: &str, (c, len): (char, usize)) -> bool {
+    let trimmed = line.trim();
+    let run = trimmed.chars().take_while(|&x| x == c).count();
+    run >= len && run == trimmed.len()
+}
+
+/// Level and text of an ATX heading line like `## Backend`.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Whether the line is a list item with a checkbox.
+fn is_task(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    ["- [", "* [", "+ ["].iter().any(|m| trimmed.starts_with(m))
+}
+
+/// The date of a `<!-- due: 2025-12-01 -->` style comment, `due` preferred over `start`.
+fn comment_date(line: &str) -> Option<NaiveDate> {
+    let inner = line.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    let find = |wanted: &str| {
+        inner.split([',', ';']).find_map(|part| {
+            let (key, value) = part.split_once(':')?;
+            (key.trim() == wanted).then(|| value.trim())
+        })
+    };
+    let value = find("due").or_else(|| find("start"))?;
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn done_early_threshold() {
+        let today = date("2025-03-10");
+        let checked = Item::from_line("- [x] ship it <agmd:2025-03-17>", 1, vec![]);
+        assert!(!checked.done_early(today, 7));
+        assert!(checked.done_early(today, 6));
+        assert!(checked.done_early(date("2025-03-09"), 7));
+
+        let open = Item::from_line("- [ ] ship it <agmd:2025-06-01>", 1, vec![]);
+        assert!(!open.done_early(today, 7));
+        let undated = Item::from_line("- [x] ship it <agmd:soon>", 1, vec![]);
+        assert!(!undated.done_early(today, 7));
+    }
+
+    #[test]
+    fn unclosed_markers_are_malformed() {
+        let item = Item::from_line("- [ ] ship it <agmd:2025-03-10", 1, vec![]);
+        assert!(item.malformed);
+        assert_eq!(item.date, None);
+        assert_eq!(
+            item.error.as_deref(),
+            Some("unclosed marker, expect <agmd:YYYY-MM-DD>")
+        );
+
+        // a closed marker on the same line still dates it
+        let item = Item::from_line("- [ ] ship it <agmd:2025-03-10> <agmd:later", 1, vec![]);
+        assert!(!item.malformed);
+        assert_eq!(item.error, None);
+    }
+
+    #[test]
+    fn fences_close_on_their_own_marker() {
+        assert_eq!(opening_fence("```sh"), Some(('`', 3)));
+        assert_eq!(opening_fence("  ~~~~ markdown"), Some(('~', 4)));
+        assert_eq!(opening_fence("``not a fence"), None);
+        // backticks in the info string make it inline code
+        assert_eq!(opening_fence("``` a ` b"), None);
+        assert_eq!(opening_fence("~~~ a ` b"), Some(('~', 3)));
+
+        assert!(closes_fence("```", ('`', 3)));
+        assert!(closes_fence("  `````  ", ('`', 3)));
+        // shorter runs, the other character and trailing text do not close
+        assert!(!closes_fence("```", ('`', 4)));
+        assert!(!closes_fence("~~~", ('`', 3)));
+        assert!(!closes_fence("```sh", ('`', 3)));
+    }
+
+    #[test]
+    fn uncheck_round_trip() {
+        let text = "# Ops\r\n- [x] rotate keys <agmd:2025-06-01>\r\n- [x] other\r\n";
+        let unchecked = uncheck_line(text, 2).unwrap();
+        assert_eq!(
+            unchecked,
+            "# Ops\r\n- [ ] rotate keys <agmd:2025-06-01>\r\n- [x] other\r\n"
+        );
+        let line = unchecked.lines().nth(1).unwrap();
+        assert!(!Item::from_line(line, 2, vec![]).done);
+
+        assert_eq!(uncheck_line(&unchecked, 2), None);
+        assert_eq!(uncheck_line(text, 1), None);
+        assert_eq!(uncheck_line(text, 0), None);
+        assert_eq!(uncheck_line(text, 9), None);
+    }
+
+    #[test]
+    fn assignees_from_mentions() {
+        let line = "- [ ] sync with @alice and @bob.smith, mail carol@example.com `@code`";
+        let item = Item::from_line(line, 1, vec![]);
+        assert_eq!(item.assignees, ["alice", "bob.smith"]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use chrono::{NaiveDate, Weekday};
+use serde::Serialize;
+
+use crate::parse::Item;
+
+/// Aggregate numbers over all items, whatever their date.
+#[derive(Serialize, Debug, Default)]
+pub struct Stats {
+    pub total: usize,
+    pub open: usize,
+    pub done: usize,
+    /// Items whose markers hold no date.
+    pub malformed: usize,
+    pub dated: usize,
+    /// Items without a date, malformed ones included and someday ones with --someday.
+    pub undated: usize,
+    /// Date of the longest overdue open item.
+    pub oldest_overdue: Option<NaiveDate>,
+    /// The week from today on with the most open items.
+    pub busiest_week: Option<BusyWeek>,
+    /// The files that took longest to parse, slowest first, only in JSON.
+    pub slowest_files: Vec<SlowFile>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BusyWeek {
+    /// Monday of the week.
+    pub start: NaiveDate,
+    pub items: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SlowFile {
+    pub path: String,
+    /// Parse time in milliseconds.
+    pub ms: f64,
+    pub items: usize,
+}
+
+impl Stats {
+    pub fn new<'a>(items: impl IntoIterator<Item = &'a Item>, today: NaiveDate) -> Self {
+        let mut stats = Stats::default();
+        let mut weeks: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for item in items {
+            stats.total += 1;
+            match item.done {
+                true => stats.done += 1,
+                false => stats.open += 1,
+            }
+            stats.malformed += item.malformed as usize;
+            let Some(date) = item.date else {
+                stats.undated += 1;
+                continue;
+            };
+            stats.dated += 1;
+            if item.overdue_days(today).is_some() {
+                stats.oldest_overdue = Some(stats.oldest_overdue.map_or(date, |d| d.min(date)));
+            }
+            if !item.done && date >= today {
+                *weeks
+                    .entry(date.week(Weekday::Mon).first_day())
+                    .or_default() += 1;
+            }
+        }
+
+        // the earliest week wins a tie
+        stats.busiest_week =
+            weeks
+                .into_iter()
+                .fold(None, |best: Option<BusyWeek>, (start, items)| match best {
+                    Some(best) if best.items >= items => Some(best),
+                    _ => Some(BusyWeek { start, items }),
+                });
+        stats
+    }
+
+    /// Write the numbers as an aligned two-column table.
+    pub fn write_table(&self, w: &mut impl Write) -> io::Result<()> {
+        let none = || "—".to_string();
+        let rows = [
+            ("total", self.total.to_string()),
+            ("open", self.open.to_string()),
+            ("done", self.done.to_string()),
+            ("malformed", self.malformed.to_string()),
+            ("dated", self.dated.to_string()),
+            ("undated", self.undated.to_string()),
+            (
+                "oldest overdue",
+                self.oldest_overdue.map_or_else(none, |d| d.to_string()),
+            ),
+            (
+                "busiest week",
+                self.busiest_week.as_ref().map_or_else(none, |week| {
+                    format!("{} ({} items)", week.start.format("%Y-%m-%d"), week.items)
+                }),
+            ),
+        ];
+        for (name, value) in rows {
+            writeln!(w, "{name:<16}{value}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,60 @@
-use ignore::{DirEntry, Error, Walk, WalkBuilder, types::TypesBuilder};
+use std::path::{Path, PathBuf};
 
-pub fn build_walk() -> Walk {
-    let types = TypesBuilder::new()
-        .add_defaults()
-        .select("markdown")
-        .build()
-        .unwrap();
+use ignore::{
+    DirEntry, Error, WalkBuilder,
+    overrides::{Override, OverrideBuilder},
+    types::TypesBuilder,
+};
 
-    WalkBuilder::new(".").types(types).build()
+/// Extensions of the markdown files that are walked and watched.
+const MARKDOWN_EXTENSIONS: [&str; 7] = ["md", "markdown", "mdown", "mdwn", "mkd", "mkdn", "mdx"];
+
+/// Walk the markdown files under each root in turn.
+///
+/// `include` and `exclude` globs are matched against paths relative to the
+/// root they are found under.
+pub fn build_walk<'a>(
+    roots: &'a [PathBuf],
+    include: &'a [String],
+    exclude: &'a [String],
+) -> impl Iterator<Item = Result<DirEntry, Error>> + 'a {
+    let mut types = TypesBuilder::new();
+    for ext in MARKDOWN_EXTENSIONS {
+        types.add("markdown", &format!("*.{ext}")).unwrap();
+    }
+    let types = types.select("markdown").build().unwrap();
+
+    roots.iter().flat_map(move |root| {
+        let overrides = build_overrides(root, include, exclude).unwrap();
+        WalkBuilder::new(root)
+            .types(types.clone())
+            .overrides(overrides)
+            .build()
+    })
+}
+
+/// Overrides keeping the files matching any `include` glob, all if there is
+/// none, and dropping those matching an `exclude` glob.
+pub fn build_overrides(
+    root: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Override, Error> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in include {
+        builder.add(glob)?;
+    }
+    // the last matching glob wins, so excludes go last
+    for glob in exclude {
+        builder.add(&format!("!{glob}"))?;
+    }
+    builder.build()
+}
+
+/// Whether `path` has one of the [`MARKDOWN_EXTENSIONS`].
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| MARKDOWN_EXTENSIONS.iter().any(|m| ext == *m))
 }
 
 pub fn entry_is_file(entry: &DirEntry) -> bool {
@@ -17,9 +64,43 @@ pub fn entry_is_file(entry: &DirEntry) -> bool {
     }
 }
 
-pub fn build_walk_filtered() -> impl Iterator<Item = Result<DirEntry, Error>> {
-    build_walk().filter(|r| match r {
+pub fn build_walk_filtered<'a>(
+    roots: &'a [PathBuf],
+    include: &'a [String],
+    exclude: &'a [String],
+) -> impl Iterator<Item = Result<DirEntry, Error>> + 'a {
+    build_walk(roots, include, exclude).filter(|r| match r {
         Ok(entry) => entry_is_file(entry),
         Err(_) => true,
     })
 }
+
+/// Whether `root` looks to be on a case-insensitive filesystem.
+///
+/// Probes by flipping the case of the last component and checking that the
+/// other spelling still exists.
+pub fn is_case_insensitive(root: &Path) -> bool {
+    let Ok(canonical) = root.canonicalize() else {
+        return false;
+    };
+    let Some(name) = canonical.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let flipped: String = name
+        .chars()
+        .map(|c| match c.is_uppercase() {
+            true => c.to_ascii_lowercase(),
+            false => c.to_ascii_uppercase(),
+        })
+        .collect();
+    flipped != name && canonical.with_file_name(flipped).exists()
+}
+
+/// The key identifying a file across roots, ignoring case if asked.
+pub fn dedupe_key(path: &Path, ignore_case: bool) -> PathBuf {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match ignore_case {
+        true => PathBuf::from(canonical.to_string_lossy().to_lowercase()),
+        false => canonical,
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf, absolute},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{Result, eyre::Context};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::walk::is_markdown;
+
+/// How long the roots must stay quiet before a burst of changes counts as one.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// The longest a steady stream of changes can hold back a reload.
+const MAX_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// File names whose changes affect which files are scanned.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// What a burst of changes touched.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Only these markdown files, as absolute paths.
+    Files(HashSet<PathBuf>),
+    /// Anything, like an ignore file or events notify lost track of.
+    All,
+}
+
+impl Change {
+    fn merge(self, other: Change) -> Change {
+        match (self, other) {
+            (Change::Files(mut paths), Change::Files(more)) => {
+                paths.extend(more);
+                Change::Files(paths)
+            }
+            _ => Change::All,
+        }
+    }
+}
+
+/// Watches the scanned roots for changes to markdown and ignore files.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// Directory roots watched recursively, as reported by notify and as scanned.
+    dirs: Vec<(PathBuf, PathBuf)>,
+    /// Single-file roots, watched through their directory so a save by rename is seen.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Watcher {
+    pub fn new(roots: &[PathBuf]) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).wrap_err("failed to start watching")?;
+        let (mut dirs, mut files) = (vec![], vec![]);
+        for root in roots {
+            // notify may report paths with symlinks resolved
+            let paths = root
+                .canonicalize()
+                .and_then(|real| Ok((real, absolute(root)?)));
+            let (real, scanned) =
+                paths.wrap_err_with(|| format!("failed to watch {}", root.display()))?;
+            let (watched, mode) = match real.parent() {
+                Some(dir) if real.is_file() => (dir, RecursiveMode::NonRecursive),
+                _ => (real.as_path(), RecursiveMode::Recursive),
+            };
+            watcher
+                .watch(watched, mode)
+                .wrap_err_with(|| format!("failed to watch {}", root.display()))?;
+            match real.is_file() {
+                true => files.push((real, scanned)),
+                false => dirs.push((real, scanned)),
+            }
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            dirs,
+            files,
+        })
+    }
+
+    /// What changed since the last call, waiting for a burst of changes to settle.
+    pub fn changed(&self) -> Option<Change> {
+        let mut change = None;
+        let mut first = None;
+        let mut timeout = Duration::ZERO;
+        while let Ok(event) = self.events.recv_timeout(timeout) {
+            let Some(found) = self.change(event) else {
+                continue;
+            };
+            change = Some(match change {
+                Some(change) => found.merge(change),
+                None => found,
+            });
+            let first = *first.get_or_insert_with(Instant::now);
+            // a file written without pause still shows up now and then
+            timeout = DEBOUNCE.min(MAX_DEBOUNCE.saturating_sub(first.elapsed()));
+            if timeout.is_zero() {
+                break;
+            }
+        }
+        change
+    }
+
+    /// What `event` changed among the scanned files, if anything.
+    fn change(&self, event: notify::Result<Event>) -> Option<Change> {
+        let event = match event {
+            Ok(event) if event.need_rescan() => return Some(Change::All),
+            Ok(event) => event,
+            Err(_) => return Some(Change::All),
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return None;
+        }
+        let mut paths = HashSet::new();
+        for path in event.paths {
+            match self.scanned(&path) {
+                Some(scanned) if is_markdown(&scanned) => paths.insert(scanned),
+                Some(_) => return Some(Change::All),
+                None => continue,
+            };
+        }
+        (!paths.is_empty()).then_some(Change::Files(paths))
+    }
+
+    /// `path` as the scan sees it, if it is a scanned file or a file of a scanned directory
+    /// worth watching.
+    fn scanned(&self, path: &Path) -> Option<PathBuf> {
+        let file = (self.files.iter()).find(|(real, _)| real == path);
+        if let Some((_, scanned)) = file {
+            return Some(scanned.clone());
+        }
+        (self.dirs.iter())
+            .find_map(|(real, scanned)| Some(scanned.join(path.strip_prefix(real).ok()?)))
+            .filter(|path| is_watched(path))
+    }
+}
+
+fn is_watched(path: &Path) -> bool {
+    let ignore = path
+        .file_name()
+        .is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f));
+    is_markdown(path) || ignore
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, thread};
+
+    use super::*;
+
+    #[test]
+    fn watches_what_is_walked() {
+        for path in [
+            "a/todo.md",
+            "a/todo.markdown",
+            "a/todo.mdx",
+            "a/todo.mkd",
+            "a/.ignore",
+        ] {
+            assert!(is_watched(Path::new(path)), "{path}");
+        }
+        for path in ["a/todo.txt", "a/md", "a/.md.swp"] {
+            assert!(!is_watched(Path::new(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn sees_a_file_root_saved_by_rename() {
+        let dir = env::temp_dir().join(format!("utfq-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("todo.md");
+        fs::write(&file, "- [ ] before").unwrap();
+        let watcher = Watcher::new(std::slice::from_ref(&file)).unwrap();
+
+        // a sibling is not scanned, the temporary file is renamed over the root
+        fs::write(dir.join("other.md"), "- [ ] elsewhere").unwrap();
+        fs::write(dir.join("todo.md.tmp"), "- [ ] after").unwrap();
+        fs::rename(dir.join("todo.md.tmp"), &file).unwrap();
+
+        let change = (0..20).find_map(|_| {
+            thread::sleep(Duration::from_millis(50));
+            watcher.changed()
+        });
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(change, Some(Change::Files(HashSet::from([file]))));
+    }
+
+    #[test]
+    fn steady_writes_still_report_changes() {
+        let dir = env::temp_dir().join(format!("utfq-watch-steady-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let watcher = Watcher::new(std::slice::from_ref(&dir)).unwrap();
+
+        let file = dir.join("log.md");
+        let writer = thread::spawn(move || {
+            for i in 0..40 {
+                fs::write(&file, format!("- [ ] entry {i}")).unwrap();
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        let change = watcher.changed();
+        let waited = start.elapsed();
+        writer.join().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(matches!(change, Some(Change::Files(_))), "{change:?}");
+        assert!(waited < MAX_DEBOUNCE + DEBOUNCE, "{waited:?}");
+    }
+}
@@ -0,0 +1,689 @@
+//! End-to-end tests running the built binary against the fixture vault.
+//!
+//! A new case is a call to [`utfq`] with the flags under test and an inline
+//! snapshot of what it prints. Dates in the vault are all in the past, so
+//! the overdue numbers do not depend on the day the tests run.
+
+use std::{
+    env,
+    fmt::Write,
+    fs, io,
+    path::Path,
+    process,
+    time::{Duration, SystemTime},
+};
+
+use assert_cmd::Command;
+use insta::assert_snapshot;
+
+/// The checked-in vault most tests run against.
+const VAULT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vault");
+/// The date viewed by [`utfq`], a Monday.
+const DATE: &str = "2025-03-10";
+
+/// utfq run in `dir`, away from the user's config file and environment.
+fn command(dir: &Path) -> Command {
+    Command::from_std(std_command(dir))
+}
+
+/// [`command`] without the assertions, for control over the stdio.
+fn std_command(dir: &Path) -> process::Command {
+    let mut cmd = process::Command::new(env!("CARGO_BIN_EXE_utfq"));
+    cmd.current_dir(dir).env("XDG_CONFIG_HOME", dir);
+    for var in [
+        "UTFQ_COLOR",
+        "UTFQ_FORMAT",
+        "UTFQ_ROOT",
+        "UTFQ_PROFILE",
+        "NO_COLOR",
+        "RUST_LOG",
+    ] {
+        cmd.env_remove(var);
+    }
+    cmd
+}
+
+/// The stdout of a successful run in `dir`.
+fn stdout_in(dir: &Path, args: &[&str]) -> String {
+    let assert = command(dir).args(args).assert().success();
+    String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+}
+
+/// The stdout of a successful run over the vault, viewing [`DATE`].
+fn utfq(args: &[&str]) -> String {
+    let args: Vec<_> = ["--date", DATE].iter().chain(args).copied().collect();
+    stdout_in(Path::new(VAULT), &args)
+}
+
+#[test]
+fn lists_the_viewed_date() {
+    assert_snapshot!(utfq(&["--format", "todotxt"]), @r"
+    real task after the fences due:2025-03-10 +code
+    after the long fence due:2025-03-10 +code
+    double quoted due:2025-03-10 +forms
+    single quoted due:2025-03-10 +forms
+    bracketed due:2025-03-10 +forms
+    two markers due:2025-03-09 +forms
+    star bullet due:2025-03-10 +forms
+    1. numbered due:2025-03-10 +forms
+    call the plumber due:2025-03-10 +inbox
+    standup notes due:2025-03-10 +2025-03
+    good one due:2025-03-10 +malformed
+    ship the v2 endpoints #api @alice due:2025-03-10 +backend
+    write the migration due:2025-03-10 +backend
+    rotate keys #security due:2025-03-03 +backend
+    inside the region due:2025-03-10 +regions
+    ");
+}
+
+#[test]
+fn counts_per_file() {
+    assert_snapshot!(utfq(&["--count"]), @r"
+        2  code.md
+        6  forms.md
+        1  inbox.md
+        1  journal/2025-03.markdown
+        1  malformed.md
+        3  projects/backend.md
+        1  regions.md
+    15 total (15 overdue, 2 done hidden)
+    ");
+}
+
+#[test]
+fn all_skips_ignored_files_pragmas_and_fences() {
+    assert_snapshot!(utfq(&["--all", "--template", "{file}:{line}"]), @r"
+    code.md:11
+    code.md:20
+    forms.md:3
+    forms.md:4
+    forms.md:5
+    forms.md:7
+    forms.md:8
+    forms.md:9
+    inbox.md:3
+    inbox.md:4
+    journal/2025-03.markdown:3
+    malformed.md:3
+    malformed.md:4
+    malformed.md:5
+    malformed.md:6
+    malformed.md:7
+    old.md:1
+    projects/backend.md:5
+    projects/backend.md:6
+    projects/backend.md:7
+    projects/backend.md:11
+    projects/frontend.md:4
+    regions.md:3
+    ");
+}
+
+#[test]
+fn pragmas_cover_their_own_line() {
+    let args = [
+        "--all",
+        "--include",
+        "pragma.md",
+        "--include",
+        "regions.md",
+        "--format",
+        "todotxt",
+        "-vv",
+    ];
+    let assert = command(Path::new(VAULT)).args(args).assert().success();
+    let output = assert.get_output();
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    assert_snapshot!(stdout, @"inside the region due:2025-03-10 +regions");
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    assert!(
+        stderr.contains("pragma.md: utfq: ignore pragma"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn done_items() {
+    let output = utfq(&["--done", "--format", "csv", "--include", "projects/**"]);
+    assert_snapshot!(output, @r"
+    file,line,done,date,text
+    projects/backend.md,5,false,2025-03-10,- [ ] ship the v2 endpoints <agmd:2025-03-10> #api @alice
+    projects/backend.md,6,false,2025-03-10,- [ ] write the migration <agmd:2025-03-10>
+    projects/backend.md,11,false,2025-03-03,- [ ] rotate keys <agmd:2025-03-03> <agmd:2025-03-10> #security
+    projects/frontend.md,3,true,2025-03-10,- [x] fix the login redirect <agmd:2025-03-10>
+    ");
+}
+
+#[test]
+fn nested_lists_keep_their_headings() {
+    let template = "{file}:{line} {due} [{context}] {summary}";
+    let output = utfq(&["--all", "--include", "projects/**", "--template", template]);
+    assert_snapshot!(output, @r"
+    projects/backend.md:5 2025-03-10 [Backend > API] ship the v2 endpoints #api @alice
+    projects/backend.md:6 2025-03-10 [Backend > API] write the migration
+    projects/backend.md:7 2025-03-11 [Backend > API] review with @bob
+    projects/backend.md:11 2025-03-03 [Backend > Ops] rotate keys #security
+    projects/frontend.md:4 2025-03-14 [Frontend] polish the dashboard
+    ");
+}
+
+#[test]
+fn malformed_markers() {
+    let template = "{file}:{line}: {summary}";
+    let output = stdout_in(Path::new(VAULT), &["--malformed", "--template", template]);
+    assert_snapshot!(output, @r"
+    malformed.md:3: month thirteen
+    malformed.md:4: keyed value
+    malformed.md:5: plain words
+    malformed.md:6: unclosed marker <agmd:2025-03-10
+    ");
+}
+
+#[test]
+fn someday_items() {
+    assert_snapshot!(utfq(&["--only-someday", "--format", "todotxt"]), @"someday maybe +forms");
+}
+
+#[test]
+fn tags_and_assignees() {
+    let output = utfq(&["--all", "--tag", "api", "--format", "todotxt"]);
+    assert_snapshot!(output, @"ship the v2 endpoints #api @alice due:2025-03-10 +backend");
+    let output = utfq(&["--all", "--assignee", "bob", "--format", "todotxt"]);
+    assert_snapshot!(output, @"review with @bob due:2025-03-11 +backend");
+}
+
+#[test]
+fn html_comment_dates() {
+    let output = utfq(&[
+        "--html-comment-dates",
+        "--include",
+        "comments.md",
+        "--format",
+        "todotxt",
+    ]);
+    assert_snapshot!(output, @"water the plants due:2025-03-10 +comments");
+    let args = ["--html-comment-dates", "--include", "comments.md", "--week"];
+    assert!(utfq(&args).contains("water the plants  (from comment: 2025-03-10)"));
+}
+
+#[test]
+fn json() {
+    // a copy with a known modification time
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("json");
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(Path::new(VAULT).join("inbox.md"), dir.join("inbox.md")).unwrap();
+    let file = fs::File::options()
+        .write(true)
+        .open(dir.join("inbox.md"))
+        .unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1741597200))
+        .unwrap();
+    let args = ["--date", DATE, "--format", "json"];
+    assert_snapshot!(stdout_in(&dir, &args), @r#"
+    [
+      {
+        "path": "inbox.md",
+        "done": false,
+        "text": "- [ ] call the plumber <agmd:2025-03-10>",
+        "date": "2025-03-10",
+        "date_source": null,
+        "malformed": false,
+        "error": null,
+        "tags": [],
+        "assignees": [],
+        "line": 3,
+        "context": [
+          "Inbox"
+        ],
+        "overdue_bucket": "on-time",
+        "file_mtime": "2025-03-10T09:00:00Z"
+      }
+    ]
+    "#);
+}
+
+#[test]
+fn forecast_only_widens_the_text_view() {
+    // nothing is due on that Wednesday
+    let empty_day = |format: &str| {
+        let date = ["--date", "2025-03-12"];
+        let args = ["--include", "projects/**", "--forecast", "--format", format];
+        stdout_in(Path::new(VAULT), &[&date[..], &args].concat())
+    };
+    assert_snapshot!(empty_day("text"), @r"
+    nothing on 2025-03-12; showing next 7 days
+    projects/frontend.md
+      4: - [ ] polish the dashboard <agmd:2025-03-14>  — Frontend
+    ");
+    assert_snapshot!(empty_day("json"), @"[]");
+}
+
+#[test]
+fn forecast_looks_past_items_hidden_by_grep() {
+    let args = [
+        "--include",
+        "projects/**",
+        "--forecast",
+        "--grep",
+        "polish",
+        "--format",
+        "text",
+    ];
+    assert_snapshot!(utfq(&args), @r"
+    nothing on 2025-03-10; showing next 7 days
+    projects/frontend.md
+      4: - [ ] polish the dashboard <agmd:2025-03-14>  — Frontend
+    ");
+}
+
+#[test]
+fn ics_day_files_are_pruned_within_the_viewed_date() {
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ics-days");
+    let _ = fs::remove_dir_all(&out);
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("2025-03-01.ics"), "").unwrap();
+    let files = || {
+        let mut names: Vec<_> = fs::read_dir(&out)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names.join("\n")
+    };
+
+    let args = ["--include", "projects/**", "--format", "ics", "--out"];
+    utfq(&[&args[..], &[out.to_str().unwrap()]].concat());
+    assert_snapshot!(files(), @r"
+    2025-03-01.ics
+    2025-03-03.ics
+    2025-03-10.ics
+    index.ics
+    ");
+
+    // the viewed date has no items anymore, other days are left alone
+    utfq(&[&args[..], &[out.to_str().unwrap(), "--grep", "nothing"]].concat());
+    assert_snapshot!(files(), @r"
+    2025-03-01.ics
+    2025-03-03.ics
+    index.ics
+    ");
+}
+
+#[test]
+fn grouped_by_overdue_bucket() {
+    let args = [
+        "--date",
+        "2025-04-05",
+        "--all",
+        "--include",
+        "projects/**",
+        "--group-by",
+        "overdue",
+        "--no-context",
+        "--format",
+        "text",
+    ];
+    assert_snapshot!(stdout_in(Path::new(VAULT), &args), @r"
+    <3m
+      - [ ] rotate keys <agmd:2025-03-03> <agmd:2025-03-10> #security  (projects/backend.md:11)
+    <1m
+      - [ ] ship the v2 endpoints <agmd:2025-03-10> #api @alice  (projects/backend.md:5)
+      - [ ] write the migration <agmd:2025-03-10>  (projects/backend.md:6)
+      - [ ] review with @bob <agmd:2025-03-11>  (projects/backend.md:7)
+      - [ ] polish the dashboard <agmd:2025-03-14>  (projects/frontend.md:4)
+    ");
+    let template = "{overdue_bucket} {summary}";
+    let output = utfq(&["--include", "inbox.md", "--template", template]);
+    assert_snapshot!(output, @"on-time call the plumber");
+}
+
+#[test]
+fn grouped_by_dir() {
+    let args = [
+        "--date",
+        "2025-03-12",
+        "--all",
+        "--include",
+        "journal/**",
+        "--include",
+        "projects/**",
+        "--group-by",
+        "dir",
+        "--no-context",
+        "--format",
+        "text",
+    ];
+    assert_snapshot!(stdout_in(Path::new(VAULT), &args), @r"
+    journal (1 open, 1 overdue)
+      2025-03.markdown:3: - [ ] standup notes <agmd:2025-03-10>
+    projects (5 open, 4 overdue)
+      backend.md:5: - [ ] ship the v2 endpoints <agmd:2025-03-10> #api @alice
+      backend.md:6: - [ ] write the migration <agmd:2025-03-10>
+      backend.md:7: - [ ] review with @bob <agmd:2025-03-11>
+      backend.md:11: - [ ] rotate keys <agmd:2025-03-03> <agmd:2025-03-10> #security
+      frontend.md:4: - [ ] polish the dashboard <agmd:2025-03-14>
+    ");
+}
+
+#[test]
+fn week_agenda() {
+    assert_snapshot!(utfq(&["--include", "projects/**", "--week"]), @r"
+    Monday 2025-03-10
+      - [ ] ship the v2 endpoints <agmd:2025-03-10> #api @alice  — Backend > API  (projects/backend.md:5)
+      - [ ] write the migration <agmd:2025-03-10>  — Backend > API  (projects/backend.md:6)
+    Tuesday 2025-03-11
+      - [ ] review with @bob <agmd:2025-03-11>  — Backend > API  (projects/backend.md:7)
+    Wednesday 2025-03-12
+    Thursday 2025-03-13
+    Friday 2025-03-14
+      - [ ] polish the dashboard <agmd:2025-03-14>  — Frontend  (projects/frontend.md:4)
+    Saturday 2025-03-15
+    Sunday 2025-03-16
+    ");
+}
+
+#[test]
+fn calendar_starts_on_week_start() {
+    // stdout is a pipe, so no color codes either
+    let args = [
+        "--include",
+        "projects/**",
+        "--week-start",
+        "sunday",
+        "--calendar=2025-03",
+    ];
+    let output = utfq(&args);
+    let lines: Vec<_> = output.lines().map(str::trim_end).collect();
+    assert_snapshot!(lines.join("\n"), @r"
+                       March 2025
+    Su     Mo     Tu     We     Th     Fr     Sa
+                                               1
+     2      3!1    4      5      6      7      8
+     9     10!2   11!1   12     13     14!1   15
+    16     17     18     19     20     21     22
+    23     24     25     26     27     28     29
+    30     31
+    ");
+}
+
+#[test]
+fn stdin() {
+    let assert = command(Path::new(VAULT))
+        .args(["-", "--date", DATE, "--format", "todotxt"])
+        .write_stdin("- [ ] from a pipe <agmd:2025-03-10>\n")
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert_snapshot!(output, @"from a pipe due:2025-03-10");
+}
+
+#[test]
+fn grouped_by_assignee() {
+    let tasks = "\
+- [ ] plan the offsite @alice <agmd:2025-03-10>
+- [ ] review with @bob and @robert <agmd:2025-03-10>
+- [ ] pair with @alice and @Rob <agmd:2025-03-10>
+- [ ] water the plants <agmd:2025-03-10>
+";
+    let run = |args: &[&str]| {
+        let assert = command(Path::new(VAULT))
+            .args([
+                "-",
+                "--date",
+                DATE,
+                "--alias",
+                "bob=robert",
+                "--alias",
+                "rob=robert",
+            ])
+            .args(args)
+            .write_stdin(tasks)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+    };
+
+    let args = ["--group-by", "assignee", "--no-context", "--format", "text"];
+    assert_snapshot!(run(&args), @r"
+    @alice
+      - [ ] plan the offsite @alice <agmd:2025-03-10>  (<stdin>:1)
+      - [ ] pair with @alice and @Rob <agmd:2025-03-10>  (<stdin>:3)
+    @robert
+      - [ ] review with @bob and @robert <agmd:2025-03-10>  (<stdin>:2)
+      - [ ] pair with @alice and @Rob <agmd:2025-03-10>  (<stdin>:3)
+    unassigned
+      - [ ] water the plants <agmd:2025-03-10>  (<stdin>:4)
+    ");
+    assert_snapshot!(run(&["--assignee", "bob", "--format", "todotxt"]), @r"
+    review with @bob and @robert due:2025-03-10
+    pair with @alice and @Rob due:2025-03-10
+    ");
+}
+
+#[test]
+fn profiles_sit_between_the_file_and_the_environment() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("profiles");
+    fs::create_dir_all(dir.join("utfq")).unwrap();
+    let config = format!(
+        "format = \"csv\"
+include = [\"inbox.md\"]
+
+[profile.work]
+root = {VAULT:?}
+format = \"todotxt\"
+"
+    );
+    fs::write(dir.join("utfq/config.toml"), config).unwrap();
+    let run = |envs: &[(&str, &str)], args: &[&str]| {
+        let assert = command(&dir)
+            .envs(envs.iter().copied())
+            .args(["--date", DATE])
+            .args(args)
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        stdout.replace(VAULT, "<vault>")
+    };
+
+    // the file alone scans the working directory
+    assert_snapshot!(run(&[], &[]), @"file,line,done,date,text");
+    assert_snapshot!(run(&[], &["--profile", "work"]), @"call the plumber due:2025-03-10 +inbox");
+    let work = [("UTFQ_PROFILE", "work"), ("UTFQ_FORMAT", "json")];
+    assert!(run(&work, &[]).starts_with('['));
+    assert_snapshot!(run(&work, &["--format", "csv"]), @r"
+    file,line,done,date,text
+    <vault>/inbox.md,3,false,2025-03-10,- [ ] call the plumber <agmd:2025-03-10>
+    ");
+    assert_eq!(run(&[], &["--profiles"]), "work: <vault>\n");
+}
+
+#[test]
+fn exit_codes() {
+    let vault = Path::new(VAULT);
+    let empty_day = ["--date", "2099-01-01", "--count"];
+    command(vault)
+        .args(empty_day)
+        .arg("--fail-if-overdue")
+        .assert()
+        .code(1);
+    command(vault)
+        .args(empty_day)
+        .arg("--fail-if-any")
+        .assert()
+        .success();
+    command(vault)
+        .args(["--date", DATE, "--count", "--fail-if-any"])
+        .assert()
+        .code(1);
+    command(vault)
+        .args(["--date", "2025-02-30", "--count"])
+        .assert()
+        .code(2);
+
+    // items --forecast brings in from later days do not count
+    let forecast = ["--date", "2025-03-12", "--forecast", "--format", "text"];
+    command(vault)
+        .args(forecast)
+        .args(["--include", "projects/**"])
+        .arg("--fail-if-any")
+        .assert()
+        .success();
+
+    // neither do files that cannot be read, but they are errors
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("unreadable");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("binary.md"), b"- [ ] \xff\xfe <agmd:2025-03-10>\n").unwrap();
+    command(&dir)
+        .args(["--date", DATE, "--count"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn missing_roots_from_the_environment_are_rejected() {
+    let missing = Path::new(VAULT).join("missing");
+    let root = env::join_paths([Path::new(VAULT), &missing]).unwrap();
+    let assert = command(Path::new(VAULT))
+        .env("UTFQ_ROOT", root)
+        .args(["--date", DATE, "--count"])
+        .assert()
+        .code(2);
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("in the environment variable UTFQ_ROOT is not an existing path"));
+}
+
+#[test]
+fn closed_pipe_is_a_clean_finish() {
+    let (reader, writer) = io::pipe().unwrap();
+    drop(reader);
+    let output = std_command(Path::new(VAULT))
+        .args(["--date", DATE, "--all", "--format", "text"])
+        .stdout(writer)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
+
+#[test]
+fn pedantic_warns_about_early_completions() {
+    let assert = command(Path::new(VAULT))
+        .args(["--date", "2025-03-02", "--count", "--pedantic"])
+        .assert()
+        .success();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("inbox.md:5: checked but due 2025-03-10, completed early?"));
+    assert!(stderr.contains("projects/frontend.md:3: checked but due 2025-03-10"));
+
+    // eight days ahead is early, seven is not
+    for (date, warned) in [("2025-03-02", true), ("2025-03-03", false)] {
+        let assert = command(Path::new(VAULT))
+            .args([
+                "--date",
+                date,
+                "--count",
+                "--pedantic",
+                "--include",
+                "inbox.md",
+            ])
+            .assert()
+            .success();
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert_eq!(stderr.contains("completed early?"), warned, "{date}");
+    }
+}
+
+#[test]
+fn pedantic_warns_about_far_dates() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("far-dates");
+    fs::create_dir_all(&dir).unwrap();
+    let text = "- [ ] fat finger <agmd:2205-12-01>\n- [ ] long ago <agmd:1925-03-10>\n";
+    fs::write(dir.join("todo.md"), text).unwrap();
+
+    let stderr = |horizon: &str| {
+        let args = ["--count", "--pedantic", "--date-horizon-years", horizon];
+        let assert = command(&dir).args(args).assert().success();
+        String::from_utf8(assert.get_output().stderr.clone()).unwrap()
+    };
+    let warned = stderr("20");
+    assert!(
+        warned.contains("todo.md:1: date 2205-12-01 is "),
+        "{warned}"
+    );
+    assert!(
+        warned.contains("todo.md:2: date 1925-03-10 was "),
+        "{warned}"
+    );
+    assert!(warned.contains("years ago — typo?"), "{warned}");
+    assert!(!stderr("300").contains("typo?"));
+}
+
+#[test]
+fn review_unchecks_early_completions() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("review");
+    fs::create_dir_all(&dir).unwrap();
+    let todo = dir.join("todo.md");
+    let text = "- [x] ship it <agmd:2025-06-01>\n- [x] way ahead <agmd:2025-09-01>\n";
+    fs::write(&todo, text).unwrap();
+
+    command(&dir)
+        .args(["--date", DATE, "--count", "--review-early"])
+        .write_stdin("y\nn\n")
+        .assert()
+        .success();
+    assert_snapshot!(fs::read_to_string(&todo).unwrap(), @r"
+    - [ ] ship it <agmd:2025-06-01>
+    - [x] way ahead <agmd:2025-09-01>
+    ");
+}
+
+#[test]
+fn slow_files_stay_out_of_the_output() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("slow");
+    fs::create_dir_all(&dir).unwrap();
+    let mut text = String::new();
+    for i in 0..20_000 {
+        writeln!(text, "- [ ] task {i} <agmd:{DATE}> #tag @name").unwrap();
+    }
+    fs::write(dir.join("big.md"), text).unwrap();
+    fs::write(dir.join("small.md"), format!("- [ ] one <agmd:{DATE}>\n")).unwrap();
+
+    let args = ["--date", DATE, "--format", "json", "--slow-files=1"];
+    let assert = command(&dir).args(args).assert().success();
+    let output = assert.get_output();
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    assert!(stdout.trim_end().ends_with(']'), "{stdout}");
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    assert_eq!(stderr.lines().count(), 1, "{stderr}");
+    assert!(stderr.contains(" ms  20000 items  big.md"), "{stderr}");
+
+    let stats = stdout_in(&dir, &["--stats", "--format", "json", "--slow-files=1"]);
+    assert!(stats.contains("\"slowest_files\": [\n    {\n      \"path\": \"big.md\""));
+    assert!(!stats.contains("small.md"), "{stats}");
+}
+
+#[test]
+fn build_info() {
+    let output = stdout_in(Path::new(VAULT), &["--build-info"]);
+    assert!(output.starts_with("utfq "), "{output}");
+}
+
+#[test]
+fn huge_file() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("huge");
+    fs::create_dir_all(&dir).unwrap();
+    let mut text = String::new();
+    for i in 0..100_000 {
+        match i % 10 {
+            0 => writeln!(text, "- [ ] task {i} <agmd:{DATE}>"),
+            _ => writeln!(text, "filler line {i}"),
+        }
+        .unwrap();
+    }
+    fs::write(dir.join("huge.md"), text).unwrap();
+
+    assert_snapshot!(stdout_in(&dir, &["--date", DATE, "--count"]), @r"
+    10000  huge.md
+    10000 total (10000 overdue, 0 done hidden)
+    ");
+}